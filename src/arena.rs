@@ -0,0 +1,53 @@
+use std::{
+    mem::{align_of, size_of},
+    ptr,
+};
+
+// Hands out memory from a fixed block front to back and never frees any of
+// it. Assets are loaded into one of these so they live in game memory along
+// with the state that refers to them.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Arena {
+    base: *mut u8,
+    size: usize,
+    used: usize,
+}
+
+impl Arena {
+    /// # Safety
+    ///
+    /// `base` has to point to `size` writable bytes that nothing else uses,
+    /// and they have to stay put for as long as the arena or anything
+    /// allocated from it is in use. Sounds and bitmaps read their data
+    /// straight from there.
+    pub unsafe fn new(base: *mut u8, size: usize) -> Arena {
+        Arena {
+            base,
+            size,
+            used: 0,
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.size - self.used
+    }
+
+    // Copies `values` into the arena. Returns None if they don't fit.
+    pub fn push_slice<T: Copy>(&mut self, values: &[T]) -> Option<*const T> {
+        let start = self.base as usize + self.used;
+        let padding = start.wrapping_neg() % align_of::<T>();
+        let bytes = values.len().checked_mul(size_of::<T>())?;
+        let end = self.used.checked_add(padding)?.checked_add(bytes)?;
+        if end > self.size {
+            return None;
+        }
+
+        unsafe {
+            let destination = self.base.add(self.used + padding) as *mut T;
+            ptr::copy_nonoverlapping(values.as_ptr(), destination, values.len());
+            self.used = end;
+            Some(destination)
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Runs the game without a compositor or audio server so it can be driven from
+// tests and CI. Game memory comes from the heap instead of the platform's
+// fixed mapping, the window always looks focused and visible, and assets are
+// read from a directory the caller names.
+//
+// Part of the headless binary rather than the game library, the tests pull it
+// in through tests/common.
+use std::{
+    fs,
+    fs::File,
+    io::{self, BufWriter, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use handmade_hero::{
+    game_play_sound, game_render, game_update, Action, ButtonState, ControllerInput, GameInput,
+    GameMemory, KeyboardInput, MouseInput, PixelBuffer, PlatformRequests, SoundBuffer, WindowState,
+    BYTES_PER_PIXEL, MAX_CONTROLLERS,
+};
+
+const PERMANENT_STORAGE_SIZE: usize = 64 * 1024 * 1024;
+const TRANSIENT_STORAGE_SIZE: usize = 128 * 1024 * 1024;
+
+// Where `read_asset` looks. The game only gets a plain function pointer, so
+// the directory handed to the latest `Headless::new` is kept here.
+static ASSETS_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub struct Headless {
+    // Backs `memory`. u64 so the game state at the start is suitably aligned.
+    _storage: Vec<u64>,
+    memory: GameMemory,
+    pub input: GameInput,
+    pub requests: PlatformRequests,
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<u8>,
+    pub sample_rate: u32,
+    pub num_channels: u8,
+}
+
+impl Headless {
+    pub fn new(
+        width: i32,
+        height: i32,
+        sample_rate: u32,
+        num_channels: u8,
+        assets_dir: &Path,
+    ) -> Headless {
+        *ASSETS_DIR.lock().unwrap() = Some(assets_dir.to_path_buf());
+
+        let size = PERMANENT_STORAGE_SIZE + TRANSIENT_STORAGE_SIZE;
+        let mut storage = vec![0u64; size / size_of::<u64>()];
+        let base = storage.as_mut_ptr() as *mut u8;
+        let mut window = WindowState::default();
+        window.activated = true;
+        window.focused = true;
+
+        Headless {
+            memory: GameMemory {
+                is_initialized: false,
+                permanent_storage_size: PERMANENT_STORAGE_SIZE,
+                permanent_storage: base,
+                transient_storage_size: TRANSIENT_STORAGE_SIZE,
+                transient_storage: unsafe { base.add(PERMANENT_STORAGE_SIZE) },
+                read_asset,
+            },
+            _storage: storage,
+            input: GameInput {
+                dt: 0.0,
+                keyboard: KeyboardInput::new(),
+                mouse: MouseInput::default(),
+                controllers: [ControllerInput::default(); MAX_CONTROLLERS],
+                actions: [ButtonState::default(); Action::COUNT],
+                window,
+            },
+            requests: PlatformRequests::default(),
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * BYTES_PER_PIXEL],
+            sample_rate,
+            num_channels,
+        }
+    }
+
+    pub fn set_action(&mut self, action: Action, is_down: bool) {
+        self.input.actions[action as usize].process(is_down);
+    }
+
+    // Runs a single update of `dt` seconds with the current input. Like the
+    // platform, transitions are only reported to the first update after them.
+    pub fn update(&mut self, dt: f32) {
+        self.input.dt = dt;
+        game_update(&mut self.memory, &self.input, &mut self.requests);
+
+        self.input.keyboard.begin_update();
+        self.input.mouse.begin_update();
+        for controller in &mut self.input.controllers {
+            controller.begin_update();
+        }
+        for action in &mut self.input.actions {
+            action.begin_update();
+        }
+    }
+
+    // Renders into `pixels`, Xrgb8888 rows without padding
+    pub fn render(&mut self) {
+        let mut pixel_buffer = PixelBuffer {
+            data: &mut self.pixels,
+            height: self.height,
+            width: self.width,
+            stride: self.width * BYTES_PER_PIXEL as i32,
+        };
+        game_render(&mut self.memory, &mut pixel_buffer);
+    }
+
+    // Asks the game for the next `frames` frames of interleaved f32 audio
+    pub fn play_sound(&mut self, frames: usize) -> Vec<f32> {
+        let num_samples = frames * self.num_channels as usize;
+        let mut data = vec![0u8; num_samples * size_of::<f32>()];
+        let mut sound_buffer = SoundBuffer {
+            data: &mut data,
+            bytes_per_sample: size_of::<f32>(),
+            sample_rate: self.sample_rate,
+            num_channels: self.num_channels,
+        };
+        game_play_sound(&mut self.memory, &mut sound_buffer);
+
+        data.chunks_exact(size_of::<f32>())
+            .map(|sample| f32::from_ne_bytes(sample.try_into().unwrap()))
+            .collect()
+    }
+}
+
+fn read_asset(name: &str) -> Result<Vec<u8>, &'static str> {
+    let path = ASSETS_DIR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .ok_or("No assets directory given")?
+        .join(name);
+    fs::read(path).map_err(|_| "Unable to read asset")
+}
+
+// Binary PPM, which needs no encoder and opens in most image viewers
+pub fn write_ppm(path: &Path, pixels: &[u8], width: i32, height: i32) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+
+    for pixel in pixels.chunks_exact(BYTES_PER_PIXEL) {
+        // Xrgb8888 is stored B, G, R, X
+        file.write_all(&[pixel[2], pixel[1], pixel[0]])?;
+    }
+
+    file.flush()
+}
+
+// 32-bit float WAV of interleaved samples
+pub fn write_wav(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    num_channels: u8,
+) -> io::Result<()> {
+    const FORMAT_IEEE_FLOAT: u16 = 3;
+    let bytes_per_sample = size_of::<f32>() as u32;
+    let data_size = samples.len() as u32 * bytes_per_sample;
+    let block_align = num_channels as u32 * bytes_per_sample;
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_size).to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+
+    file.write_all(b"fmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    file.write_all(&FORMAT_IEEE_FLOAT.to_le_bytes())?;
+    file.write_all(&(num_channels as u16).to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * block_align).to_le_bytes())?;
+    file.write_all(&(block_align as u16).to_le_bytes())?;
+    file.write_all(&(bytes_per_sample as u16 * 8).to_le_bytes())?;
+
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        file.write_all(&sample.to_le_bytes())?;
+    }
+
+    file.flush()
+}
@@ -0,0 +1,131 @@
+// Runs the game for a fixed number of frames without a compositor or audio
+// server, writing every frame as a PPM image and all audio to a WAV file.
+//
+//     headless <frames> <output directory> [script]
+//
+// Script lines are `frame action down|up`, e.g. `30 move_right down`, using
+// the action names from binding files. `#` starts a comment.
+//
+// Assets are read from $HANDMADE_HERO_ASSETS, or the repository's assets
+// directory if it isn't set.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use handmade_hero::Action;
+
+mod backend;
+
+use backend::{write_ppm, write_wav, Headless};
+
+const WIDTH: i32 = 960;
+const HEIGHT: i32 = 540;
+const SAMPLE_RATE: u32 = 48000;
+const NUM_CHANNELS: u8 = 2;
+const FRAMES_PER_SECOND: u32 = 60;
+// Same fixed step as the Wayland platform
+const UPDATES_PER_FRAME: u32 = 2;
+
+struct ScriptEvent {
+    frame: u32,
+    action: Action,
+    is_down: bool,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("usage: {} <frames> <output directory> [script]", args[0]);
+        process::exit(2);
+    }
+
+    let frames: u32 = args[1].parse().unwrap_or_else(|_| {
+        eprintln!("frames must be a number, got `{}`", args[1]);
+        process::exit(2);
+    });
+    let output = Path::new(&args[2]);
+    let script = match args.get(3) {
+        Some(path) => {
+            let text = fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Unable to read script {}: {}", path, e);
+                process::exit(1);
+            });
+            parse_script(&text)
+        }
+        None => Vec::new(),
+    };
+
+    fs::create_dir_all(output).expect("should be able to create the output directory");
+
+    let mut headless = Headless::new(WIDTH, HEIGHT, SAMPLE_RATE, NUM_CHANNELS, &assets_dir());
+    let dt = 1.0 / (FRAMES_PER_SECOND * UPDATES_PER_FRAME) as f32;
+    let audio_frames_per_frame = (SAMPLE_RATE / FRAMES_PER_SECOND) as usize;
+    let mut audio = Vec::new();
+
+    for frame in 0..frames {
+        for event in script.iter().filter(|e| e.frame == frame) {
+            headless.set_action(event.action, event.is_down);
+        }
+
+        for _ in 0..UPDATES_PER_FRAME {
+            headless.update(dt);
+        }
+
+        headless.render();
+        let path = output.join(format!("frame_{:05}.ppm", frame));
+        write_ppm(&path, &headless.pixels, WIDTH, HEIGHT).expect("should be able to write frame");
+
+        audio.extend(headless.play_sound(audio_frames_per_frame));
+    }
+
+    write_wav(&output.join("audio.wav"), &audio, SAMPLE_RATE, NUM_CHANNELS)
+        .expect("should be able to write audio");
+}
+
+fn assets_dir() -> PathBuf {
+    match env::var_os("HANDMADE_HERO_ASSETS") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"),
+    }
+}
+
+fn parse_script(text: &str) -> Vec<ScriptEvent> {
+    let mut events = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let event = match fields[..] {
+            [frame, action, state] => frame.parse().ok().and_then(|frame| {
+                let action = Action::ALL.into_iter().find(|a| a.name() == action)?;
+                let is_down = match state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return None,
+                };
+                Some(ScriptEvent {
+                    frame,
+                    action,
+                    is_down,
+                })
+            }),
+            _ => None,
+        };
+
+        match event {
+            Some(event) => events.push(event),
+            None => eprintln!(
+                "script:{}: expected `frame action down|up`",
+                line_number + 1
+            ),
+        }
+    }
+
+    events
+}
@@ -0,0 +1,240 @@
+use std::{env, fs, path::PathBuf};
+
+use handmade_hero::{Action, ButtonState, GamepadButton, Keysym};
+use xkbcommon::xkb;
+
+// Used when the player has no bindings file. Lines are `command = trigger, ...`
+// where a trigger is an xkb keysym name such as `w` or `Up`, `code:N` to bind
+// the key with evdev keycode N no matter what the layout puts on it, or
+// `pad:button` for a button on any gamepad.
+//
+// Movement is bound to where WASD and the arrows sit on the keyboard, so it
+// works the same on AZERTY, Dvorak or a non-Latin layout.
+pub(crate) const DEFAULT_BINDINGS: &str = "
+move_up = code:17, code:103, pad:dpad_up
+move_down = code:31, code:108, pad:dpad_down
+move_left = code:30, code:105, pad:dpad_left
+move_right = code:32, code:106, pad:dpad_right
+toggle_mouse_look = m
+toggle_fullscreen = F11
+toggle_maximize = F10
+next_waveform = t
+quit = q
+loop_recording = l
+";
+
+// Things a binding can do. Game actions are forwarded to the game, the rest
+// is handled by the platform layer.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+    Game(Action),
+    Quit,
+    ToggleLoopRecording,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "quit" => Some(Command::Quit),
+            "loop_recording" => Some(Command::ToggleLoopRecording),
+            _ => Action::ALL
+                .into_iter()
+                .find(|action| action.name() == name)
+                .map(Command::Game),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Trigger {
+    Keysym(Keysym),
+    Keycode(u32),
+    Gamepad(GamepadButton),
+}
+
+impl Trigger {
+    fn from_name(name: &str) -> Option<Trigger> {
+        if let Some(code) = name.strip_prefix("code:") {
+            return code.parse().ok().map(Trigger::Keycode);
+        }
+
+        if let Some(button) = name.strip_prefix("pad:") {
+            return GamepadButton::ALL
+                .into_iter()
+                .find(|b| b.name() == button)
+                .map(Trigger::Gamepad);
+        }
+
+        // Keys are matched by their unshifted keysym, so `W` means `w`
+        let name = if name.chars().count() == 1 {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        };
+
+        let mut keysym = xkb::keysym_from_name(&name, xkb::KEYSYM_NO_FLAGS).raw();
+        if keysym == 0 {
+            keysym = xkb::keysym_from_name(&name, xkb::KEYSYM_CASE_INSENSITIVE).raw();
+        }
+
+        if keysym == 0 {
+            None
+        } else {
+            Some(Trigger::Keysym(keysym))
+        }
+    }
+
+    fn matches_key(self, keysym: Keysym, keycode: u32) -> bool {
+        match self {
+            Trigger::Keysym(bound) => bound == keysym,
+            Trigger::Keycode(bound) => bound == keycode,
+            Trigger::Gamepad(_) => false,
+        }
+    }
+}
+
+struct Binding {
+    trigger: Trigger,
+    command: Command,
+    held: bool,
+}
+
+pub struct Bindings {
+    bindings: Vec<Binding>,
+}
+
+impl Bindings {
+    // Reads $XDG_CONFIG_HOME/handmade-hero/bindings (or ~/.config/...) if it
+    // exists and falls back to the default bindings otherwise.
+    pub fn load() -> Bindings {
+        let path = bindings_path();
+        match path.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(text) => {
+                eprintln!("Loading key bindings from {}", path.unwrap().display());
+                Bindings::parse(&text)
+            }
+            None => Bindings::parse(DEFAULT_BINDINGS),
+        }
+    }
+
+    pub(crate) fn parse(text: &str) -> Bindings {
+        let mut bindings = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((command, triggers)) = line.split_once('=') else {
+                eprintln!("bindings:{}: expected `command = keys`", line_number + 1);
+                continue;
+            };
+
+            let Some(command) = Command::from_name(command.trim()) else {
+                eprintln!(
+                    "bindings:{}: unknown command `{}`",
+                    line_number + 1,
+                    command.trim()
+                );
+                continue;
+            };
+
+            for trigger in triggers.split(',').map(str::trim) {
+                match Trigger::from_name(trigger) {
+                    Some(trigger) => bindings.push(Binding {
+                        trigger,
+                        command,
+                        held: false,
+                    }),
+                    None => eprintln!("bindings:{}: unknown key `{}`", line_number + 1, trigger),
+                }
+            }
+        }
+
+        Bindings { bindings }
+    }
+
+    // Updates the game actions bound to the key and returns the platform
+    // command it triggered, if any. An action stays down for as long as any
+    // key or button bound to it is held.
+    pub fn process_key(
+        &mut self,
+        keysym: Keysym,
+        keycode: u32,
+        is_down: bool,
+        actions: &mut [ButtonState; Action::COUNT],
+    ) -> Option<Command> {
+        self.process(
+            |trigger| trigger.matches_key(keysym, keycode),
+            is_down,
+            actions,
+        )
+    }
+
+    pub fn process_gamepad_button(
+        &mut self,
+        button: GamepadButton,
+        is_down: bool,
+        actions: &mut [ButtonState; Action::COUNT],
+    ) -> Option<Command> {
+        self.process(
+            |trigger| trigger == Trigger::Gamepad(button),
+            is_down,
+            actions,
+        )
+    }
+
+    // Lets go of everything bound to keys, for when keyboard focus is lost
+    // and the key releases will go to another window
+    pub fn release_keys(&mut self, actions: &mut [ButtonState; Action::COUNT]) {
+        self.process(
+            |trigger| !matches!(trigger, Trigger::Gamepad(_)),
+            false,
+            actions,
+        );
+    }
+
+    fn process(
+        &mut self,
+        matches: impl Fn(Trigger) -> bool,
+        is_down: bool,
+        actions: &mut [ButtonState; Action::COUNT],
+    ) -> Option<Command> {
+        let mut triggered = None;
+
+        for index in 0..self.bindings.len() {
+            let binding = &mut self.bindings[index];
+            if !matches(binding.trigger) {
+                continue;
+            }
+
+            binding.held = is_down;
+            let command = binding.command;
+            match command {
+                Command::Game(action) => {
+                    let action_down = self.is_held(command);
+                    actions[action as usize].process(action_down);
+                }
+                _ if is_down => triggered = Some(command),
+                _ => {}
+            }
+        }
+
+        triggered
+    }
+
+    fn is_held(&self, command: Command) -> bool {
+        self.bindings
+            .iter()
+            .any(|binding| binding.command == command && binding.held)
+    }
+}
+
+fn bindings_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("handmade-hero").join("bindings"))
+}
@@ -0,0 +1,89 @@
+use std::slice;
+
+use crate::{
+    arena::Arena,
+    image::{decode_image, Image},
+    PixelBuffer, BYTES_PER_PIXEL,
+};
+
+// An image loaded into game memory, ready to be drawn
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Bitmap {
+    // Premultiplied Argb8888 in an arena, top row first without padding
+    pixels: *const u32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Bitmap {
+    // Loads the contents of a BMP or PNG file
+    pub fn load(bytes: &[u8], arena: &mut Arena) -> Result<Bitmap, &'static str> {
+        let image = decode_image(bytes)?;
+        Bitmap::from_image(&image, arena)
+    }
+
+    pub fn from_image(image: &Image, arena: &mut Arena) -> Result<Bitmap, &'static str> {
+        let pixels = arena
+            .push_slice(&image.pixels)
+            .ok_or("Not enough memory left for the image")?;
+
+        Ok(Bitmap {
+            pixels,
+            width: image.width,
+            height: image.height,
+        })
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        // Still there by the contract of `Arena::new`
+        let len = self.width as usize * self.height as usize;
+        unsafe { slice::from_raw_parts(self.pixels, len) }
+    }
+}
+
+impl PixelBuffer<'_> {
+    // Draws `bitmap` with its top left corner at `x`, `y`, blending it over
+    // what is there by its alpha. Whatever falls outside the buffer is
+    // clipped.
+    pub fn draw_bitmap(&mut self, bitmap: &Bitmap, x: i32, y: i32) {
+        let x_start = x.max(0);
+        let x_end = x.saturating_add(bitmap.width).min(self.width);
+        let y_start = y.max(0);
+        let y_end = y.saturating_add(bitmap.height).min(self.height);
+        if x_start >= x_end || y_start >= y_end {
+            return;
+        }
+
+        let columns = (x_end - x_start) as usize;
+        let pixels = bitmap.pixels();
+        for dst_y in y_start..y_end {
+            let src_row = ((dst_y - y) * bitmap.width + (x_start - x)) as usize;
+            let src_row = &pixels[src_row..src_row + columns];
+            let dst_row = &mut self.row_mut(dst_y)[x_start as usize * BYTES_PER_PIXEL..];
+            let dst_row = &mut dst_row[..columns * BYTES_PER_PIXEL];
+
+            for (dst, &src) in dst_row.chunks_exact_mut(BYTES_PER_PIXEL).zip(src_row) {
+                let value = match src >> 24 {
+                    0 => continue,
+                    0xff => src,
+                    _ => blend(src, u32::from_le_bytes(dst.try_into().unwrap())),
+                };
+                // Xrgb8888 is stored B, G, R, X, leave X alone
+                dst[..3].copy_from_slice(&value.to_le_bytes()[..3]);
+            }
+        }
+    }
+}
+
+// Premultiplied "over": the source plus whatever of the destination shows
+// through it
+fn blend(src: u32, dst: u32) -> u32 {
+    let transparency = 255 - (src >> 24);
+    let channel = |shift: u32| {
+        let src = (src >> shift) & 0xff;
+        let dst = (dst >> shift) & 0xff;
+        (src + (dst * transparency + 127) / 255).min(255) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,105 @@
+use crate::image::{premultiply, Image, MAX_IMAGE_SIZE};
+
+// Uncompressed Windows bitmaps: 24-bit BGR and 32-bit with or without bit
+// field masks, stored bottom-up or top-down
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+const FILE_HEADER_SIZE: usize = 14;
+// The oldest info header we understand, BITMAPINFOHEADER
+const INFO_HEADER_SIZE: usize = 40;
+
+pub fn decode_bmp(bytes: &[u8]) -> Result<Image, &'static str> {
+    if bytes.len() < FILE_HEADER_SIZE + INFO_HEADER_SIZE || &bytes[0..2] != b"BM" {
+        return Err("Not a BMP file");
+    }
+
+    let pixel_offset = read_u32(bytes, 10) as usize;
+    let header_size = read_u32(bytes, 14) as usize;
+    let width = read_u32(bytes, 18) as i32;
+    let height = read_u32(bytes, 22) as i32;
+    let bits_per_pixel = read_u16(bytes, 28);
+    let compression = read_u32(bytes, 30);
+
+    if header_size < INFO_HEADER_SIZE {
+        return Err("BMP info header is too old");
+    }
+    // Negative heights store the top row first
+    let top_down = height < 0;
+    let height = height.checked_abs().ok_or("BMP image is too large")?;
+    if width <= 0 || height == 0 || width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+        return Err("BMP image has an unsupported size");
+    }
+
+    // Masks for red, green, blue and alpha. They follow the info header, or
+    // are part of it in later versions, at the same place either way.
+    let masks = match (bits_per_pixel, compression) {
+        (24, BI_RGB) => [0xff_0000, 0x00_ff00, 0x00_00ff, 0],
+        // The fourth byte is usually left at 0, so don't treat it as alpha
+        (32, BI_RGB) => [0xff_0000, 0x00_ff00, 0x00_00ff, 0],
+        (32, BI_BITFIELDS | BI_ALPHABITFIELDS) => {
+            let masks_end = FILE_HEADER_SIZE + INFO_HEADER_SIZE + 16;
+            if bytes.len() < masks_end {
+                return Err("BMP file is missing its color masks");
+            }
+            let has_alpha = compression == BI_ALPHABITFIELDS || header_size > INFO_HEADER_SIZE;
+            [
+                read_u32(bytes, 54),
+                read_u32(bytes, 58),
+                read_u32(bytes, 62),
+                if has_alpha { read_u32(bytes, 66) } else { 0 },
+            ]
+        }
+        (24 | 32, _) => return Err("Compressed BMP files are not supported"),
+        _ => return Err("Only 24 and 32-bit BMP files are supported"),
+    };
+
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    // Rows are padded to a multiple of four bytes
+    let row_size = (width as usize * bytes_per_pixel).div_ceil(4) * 4;
+    let data = bytes
+        .get(pixel_offset..)
+        .filter(|data| data.len() >= row_size * height as usize)
+        .ok_or("BMP pixel data is truncated")?;
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height as usize {
+        let row = if top_down { y } else { height as usize - 1 - y };
+        let row = &data[row * row_size..][..width as usize * bytes_per_pixel];
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            let mut value = [0; 4];
+            value[..bytes_per_pixel].copy_from_slice(pixel);
+            let value = u32::from_le_bytes(value);
+
+            let [r, g, b, a] = masks.map(|mask| channel(value, mask));
+            let a = if masks[3] == 0 { 0xff } else { a };
+            pixels.push(premultiply(r, g, b, a));
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// Scales whatever bits `mask` selects to 0..=255
+fn channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let bits = (value & mask) >> mask.trailing_zeros();
+    let max = mask >> mask.trailing_zeros();
+    (bits as u64 * 255 / max as u64) as u8
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
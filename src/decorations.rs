@@ -0,0 +1,98 @@
+use handmade_hero::PixelBuffer;
+use wayland_protocols::xdg::shell::client::xdg_toplevel::ResizeEdge;
+
+// Client-side decorations for compositors that won't draw a frame for us.
+// Sizes are in surface coordinates, the title bar is drawn at whatever scale
+// the buffer is.
+pub const TITLE_BAR_HEIGHT: i32 = 28;
+// Area along the window edges that starts an interactive resize
+const RESIZE_BORDER: i32 = 6;
+const BUTTON_ICON_SIZE: i32 = 10;
+
+// Xrgb8888
+const TITLE_BAR_ACTIVE_COLOR: u32 = 0x303030;
+const TITLE_BAR_INACTIVE_COLOR: u32 = 0x484848;
+const ICON_COLOR: u32 = 0xe0e0e0;
+const CLOSE_HOVER_COLOR: u32 = 0xc03030;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Hit {
+    // The game's part of the window
+    Content,
+    TitleBar,
+    Close,
+    Maximize,
+    Resize(ResizeEdge),
+}
+
+// What is under the pointer at `x`, `y` in a `width` x `height` window.
+// Resizing is only offered when the window isn't maximized, fullscreen or
+// tiled.
+pub fn hit_test(x: f64, y: f64, width: i32, height: i32, resizable: bool) -> Hit {
+    let (x, y) = (x as i32, y as i32);
+
+    if resizable {
+        let left = x < RESIZE_BORDER;
+        let right = x >= width - RESIZE_BORDER;
+        let top = y < RESIZE_BORDER;
+        let bottom = y >= height - RESIZE_BORDER;
+        let edge = match (top, bottom, left, right) {
+            (true, _, true, _) => Some(ResizeEdge::TopLeft),
+            (true, _, _, true) => Some(ResizeEdge::TopRight),
+            (_, true, true, _) => Some(ResizeEdge::BottomLeft),
+            (_, true, _, true) => Some(ResizeEdge::BottomRight),
+            (true, _, _, _) => Some(ResizeEdge::Top),
+            (_, true, _, _) => Some(ResizeEdge::Bottom),
+            (_, _, true, _) => Some(ResizeEdge::Left),
+            (_, _, _, true) => Some(ResizeEdge::Right),
+            _ => None,
+        };
+        if let Some(edge) = edge {
+            return Hit::Resize(edge);
+        }
+    }
+
+    if y >= TITLE_BAR_HEIGHT {
+        Hit::Content
+    } else if x >= width - TITLE_BAR_HEIGHT {
+        Hit::Close
+    } else if x >= width - 2 * TITLE_BAR_HEIGHT {
+        Hit::Maximize
+    } else {
+        Hit::TitleBar
+    }
+}
+
+// Draws the title bar over the whole of `bar`. `scale` is buffer pixels per
+// surface coordinate and `hover` is what the pointer is over.
+pub fn draw_title_bar(bar: &mut PixelBuffer, scale: f32, activated: bool, hover: Option<Hit>) {
+    let background = if activated {
+        TITLE_BAR_ACTIVE_COLOR
+    } else {
+        TITLE_BAR_INACTIVE_COLOR
+    };
+    bar.fill_rect(0, 0, bar.width, bar.height, background);
+
+    let button = bar.height;
+    let icon = (BUTTON_ICON_SIZE as f32 * scale) as i32;
+    let line = scale.round().max(1.0) as i32;
+    let inset = (button - icon) / 2;
+
+    // Close, a cross on the rightmost button
+    let close_x = bar.width - button;
+    if hover == Some(Hit::Close) {
+        bar.fill_rect(close_x, 0, button, button, CLOSE_HOVER_COLOR);
+    }
+    for i in 0..icon {
+        let x = close_x + inset + i;
+        bar.fill_rect(x, inset + i, line, line, ICON_COLOR);
+        bar.fill_rect(x, inset + icon - 1 - i, line, line, ICON_COLOR);
+    }
+
+    // Maximize, a square outline next to it
+    let x = bar.width - 2 * button + inset;
+    bar.fill_rect(x, inset, icon, line, ICON_COLOR);
+    bar.fill_rect(x, inset + icon - line, icon, line, ICON_COLOR);
+    bar.fill_rect(x, inset, line, icon, ICON_COLOR);
+    bar.fill_rect(x + icon - line, inset, line, icon, ICON_COLOR);
+}
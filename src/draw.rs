@@ -0,0 +1,357 @@
+// Drawing into pixel buffers. Colors are Xrgb8888 values like 0xff8000 and y
+// points down. Integer coordinates name pixels, float coordinates are
+// positions with pixel x, y covering x..x + 1, y..y + 1. Everything is
+// clipped to the buffer so callers can draw partly or wholly off screen.
+//
+// Rows are `stride` bytes apart, which may be more than `width` pixels. Go
+// through `row_mut` or these functions rather than working out offsets by
+// hand.
+use crate::PixelBuffer;
+
+// Pixel buffers are always Xrgb8888
+pub const BYTES_PER_PIXEL: usize = 4;
+
+impl PixelBuffer<'_> {
+    // The `width` pixels of row `y`, without any padding after them
+    pub fn row_mut(&mut self, y: i32) -> &mut [u8] {
+        let start = y as usize * self.stride as usize;
+        &mut self.data[start..start + self.width as usize * BYTES_PER_PIXEL]
+    }
+
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u32> {
+        let offset = self.offset(x, y)?;
+        let pixel = &self.data[offset..offset + BYTES_PER_PIXEL];
+        Some(u32::from_le_bytes(pixel.try_into().unwrap()) & 0xff_ffff)
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if let Some(offset) = self.offset(x, y) {
+            // Xrgb8888 is stored B, G, R, X, leave X alone
+            self.data[offset..offset + 3].copy_from_slice(&color.to_le_bytes()[..3]);
+        }
+    }
+
+    // Mixes `color` into the pixel, `coverage` 1 replaces it outright
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: u32, coverage: f32) {
+        if let Some(pixel) = self.get_pixel(x, y) {
+            self.set_pixel(x, y, lerp_color(pixel, color, coverage.clamp(0.0, 1.0)));
+        }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        self.fill_rect(0, 0, self.width, self.height, color);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        let x_start = x.clamp(0, self.width);
+        let x_end = x.saturating_add(width).clamp(0, self.width);
+        let y_start = y.clamp(0, self.height);
+        let y_end = y.saturating_add(height).clamp(0, self.height);
+
+        let color = color.to_le_bytes();
+        for y in y_start..y_end {
+            let row = self.row_mut(y);
+            let row =
+                &mut row[x_start as usize * BYTES_PER_PIXEL..x_end as usize * BYTES_PER_PIXEL];
+            for pixel in row.chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel[..3].copy_from_slice(&color[..3]);
+            }
+        }
+    }
+
+    // A one pixel outline just inside the rectangle `fill_rect` would fill
+    pub fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        let right = x.saturating_add(width - 1);
+        let bottom = y.saturating_add(height - 1);
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, bottom, width, 1, color);
+        self.fill_rect(x, y.saturating_add(1), 1, height - 2, color);
+        self.fill_rect(right, y.saturating_add(1), 1, height - 2, color);
+    }
+
+    // Bresenham, both end points included
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        // Cut the line down to the buffer first so that far off screen end
+        // points don't take forever
+        let Some((x0, y0, x1, y1)) =
+            self.clip_line(x0 as f32, y0 as f32, x1 as f32, y1 as f32, 0.0)
+        else {
+            return;
+        };
+        let (mut x, mut y) = (x0.round() as i32, y0.round() as i32);
+        let (x1, y1) = (x1.round() as i32, y1.round() as i32);
+
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            self.set_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    // Xiaolin Wu's anti-aliased line, spreading each step over the two
+    // pixels nearest to the ideal line
+    pub fn draw_line_aa(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, color: u32) {
+        // Wu works with pixel centers on whole numbers. The line bleeds into
+        // the pixels next to it, so keep those when clipping.
+        let Some((mut x0, mut y0, mut x1, mut y1)) =
+            self.clip_line(x0 - 0.5, y0 - 0.5, x1 - 0.5, y1 - 0.5, 1.0)
+        else {
+            return;
+        };
+
+        // Walk along whichever axis the line covers more of
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            (x0, y0, x1, y1) = (y0, x0, y1, x1);
+        }
+        if x0 > x1 {
+            (x0, y0, x1, y1) = (x1, y1, x0, y0);
+        }
+
+        let gradient = if x1 == x0 { 0.0 } else { (y1 - y0) / (x1 - x0) };
+        let mut plot = |major: i32, minor: i32, coverage: f32| {
+            if steep {
+                self.blend_pixel(minor, major, color, coverage);
+            } else {
+                self.blend_pixel(major, minor, color, coverage);
+            }
+        };
+
+        // End points are weighted by how much of their pixel the line covers
+        let start = x0.round();
+        let end = x1.round();
+        let mut y = y0 + gradient * (start - x0);
+        for major in start as i32..=end as i32 {
+            let weight = if start == end {
+                x1 - x0
+            } else if major == start as i32 {
+                0.5 - (x0 - start)
+            } else if major == end as i32 {
+                0.5 + (x1 - end)
+            } else {
+                1.0
+            };
+
+            let minor = y.floor();
+            let fraction = y - minor;
+            plot(major, minor as i32, (1.0 - fraction) * weight);
+            plot(major, minor as i32 + 1, fraction * weight);
+            y += gradient;
+        }
+    }
+
+    // Every pixel whose center is within `radius` of the center of pixel
+    // `cx`, `cy`
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: i32, color: u32) {
+        if radius < 0 {
+            return;
+        }
+
+        let y_start = cy.saturating_sub(radius).max(0);
+        let y_end = cy.saturating_add(radius).min(self.height - 1);
+        let radius = radius as i64;
+        for y in y_start..=y_end {
+            let dy = y as i64 - cy as i64;
+            // Rounded out a little so the edges aren't single pixel bumps
+            let half_width = ((radius * radius + radius - dy * dy) as f64).sqrt() as i32;
+            let x = cx.saturating_sub(half_width);
+            self.fill_rect(
+                x,
+                y,
+                half_width.saturating_mul(2).saturating_add(1),
+                1,
+                color,
+            );
+        }
+    }
+
+    // One pixel thick edge of what `fill_circle` fills, i.e. the pixels of
+    // the disc with a neighbour above, below or to the side outside of it.
+    // Goes over the rows on screen only, so a huge circle costs no more than
+    // one that fits.
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: i32, color: u32) {
+        if radius < 0
+            || cx.saturating_add(radius) < 0
+            || cx.saturating_sub(radius) >= self.width
+            || cy.saturating_add(radius) < 0
+            || cy.saturating_sub(radius) >= self.height
+        {
+            return;
+        }
+
+        let y_start = cy.saturating_sub(radius).max(0);
+        let y_end = cy.saturating_add(radius).min(self.height - 1);
+        let radius = radius as i64;
+        // Same as in `fill_circle`, -1 above and below the disc
+        let half_width = |dy: i64| {
+            if dy.abs() > radius {
+                -1
+            } else {
+                ((radius * radius + radius - dy * dy) as f64).sqrt() as i64
+            }
+        };
+
+        for y in y_start..=y_end {
+            let dy = y as i64 - cy as i64;
+            let outer = half_width(dy);
+            // Columns up to here have the disc above and below them as well
+            let inner = half_width(dy - 1).min(half_width(dy + 1)).min(outer - 1);
+
+            let (cx, width) = (cx as i64, self.width as i64);
+            for (start, end) in [(cx - outer, cx - inner - 1), (cx + inner + 1, cx + outer)] {
+                let (start, end) = (start.max(0), end.min(width - 1));
+                if start <= end {
+                    self.fill_rect(start as i32, y, (end - start + 1) as i32, 1, color);
+                }
+            }
+        }
+    }
+
+    // Fills the pixels whose centers are inside the triangle, blending the
+    // vertex colors by each pixel's barycentric coordinates. Pixels exactly
+    // on an edge shared by two triangles are only drawn by one of them.
+    pub fn fill_triangle(&mut self, vertices: [(f32, f32); 3], colors: [u32; 3]) {
+        let [mut a, mut b, c] = vertices;
+        let [mut color_a, mut color_b, color_c] = colors;
+
+        let mut area = edge(a, b, c);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+        // Wind every triangle the same way so inside is always positive
+        if area < 0.0 {
+            (a, b) = (b, a);
+            (color_a, color_b) = (color_b, color_a);
+            area = -area;
+        }
+
+        let x_start = a.0.min(b.0).min(c.0).floor().max(0.0) as i32;
+        let x_end = a.0.max(b.0).max(c.0).ceil().min(self.width as f32) as i32;
+        let y_start = a.1.min(b.1).min(c.1).floor().max(0.0) as i32;
+        let y_end = a.1.max(b.1).max(c.1).ceil().min(self.height as f32) as i32;
+
+        let (top_left_a, top_left_b, top_left_c) =
+            (is_top_left(b, c), is_top_left(c, a), is_top_left(a, b));
+        let colors = [color_a, color_b, color_c].map(|color| color.to_le_bytes().map(f32::from));
+
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let p = (x as f32 + 0.5, y as f32 + 0.5);
+                // How far p is from the edge opposite each vertex
+                let weight_a = edge(b, c, p);
+                let weight_b = edge(c, a, p);
+                let weight_c = edge(a, b, p);
+                if !(inside(weight_a, top_left_a)
+                    && inside(weight_b, top_left_b)
+                    && inside(weight_c, top_left_c))
+                {
+                    continue;
+                }
+
+                let weights = [weight_a / area, weight_b / area, weight_c / area];
+                let mut color = [0u8; 4];
+                for (channel, value) in color.iter_mut().enumerate() {
+                    let mixed: f32 = (0..3).map(|v| colors[v][channel] * weights[v]).sum();
+                    *value = mixed.round().clamp(0.0, 255.0) as u8;
+                }
+                self.set_pixel(x, y, u32::from_le_bytes(color));
+            }
+        }
+    }
+
+    fn offset(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(y as usize * self.stride as usize + x as usize * BYTES_PER_PIXEL)
+    }
+
+    // Liang-Barsky against the buffer grown by `margin` on every side.
+    // Returns None if none of the line is left.
+    fn clip_line(
+        &self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        margin: f32,
+    ) -> Option<(f32, f32, f32, f32)> {
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let (min_x, min_y) = (-margin, -margin);
+        let max_x = (self.width - 1) as f32 + margin;
+        let max_y = (self.height - 1) as f32 + margin;
+
+        let mut t0 = 0.0f32;
+        let mut t1 = 1.0f32;
+        for (p, q) in [
+            (-dx, x0 - min_x),
+            (dx, max_x - x0),
+            (-dy, y0 - min_y),
+            (dy, max_y - y0),
+        ] {
+            if p == 0.0 {
+                // Parallel to this side, and entirely outside it
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let t = q / p;
+                if p < 0.0 {
+                    t0 = t0.max(t);
+                } else {
+                    t1 = t1.min(t);
+                }
+            }
+        }
+
+        if t0 > t1 {
+            return None;
+        }
+        Some((x0 + t0 * dx, y0 + t0 * dy, x0 + t1 * dx, y0 + t1 * dy))
+    }
+}
+
+// Twice the signed area of the triangle a, b, p. Positive when p is on the
+// inside of a triangle wound like a, b.
+fn edge(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+// With positive winding and y down, top edges run right and left edges up
+fn is_top_left(a: (f32, f32), b: (f32, f32)) -> bool {
+    (a.1 == b.1 && b.0 > a.0) || b.1 < a.1
+}
+
+fn inside(weight: f32, top_left: bool) -> bool {
+    weight > 0.0 || (weight == 0.0 && top_left)
+}
+
+fn lerp_color(from: u32, to: u32, t: f32) -> u32 {
+    let channel = |shift: u32| {
+        let from = ((from >> shift) & 0xff) as f32;
+        let to = ((to >> shift) & 0xff) as f32;
+        ((from + (to - from) * t).round() as u32) << shift
+    };
+    channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,132 @@
+use std::{
+    env,
+    ffi::{c_void, CStr, CString},
+    fs, mem,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    process,
+    time::SystemTime,
+};
+
+use handmade_hero::{game_play_sound, game_render, game_update, PlaySoundFn, RenderFn, UpdateFn};
+use libc::{self};
+
+const GAME_LIBRARY_NAME: &str = "libhandmade_hero.so";
+
+pub struct GameCode {
+    library_path: PathBuf,
+    library: *mut c_void,
+    last_write_time: Option<SystemTime>,
+    load_count: u32,
+
+    pub update: UpdateFn,
+    pub render: RenderFn,
+    pub play_sound: PlaySoundFn,
+}
+
+impl GameCode {
+    // Starts out with the statically linked game code so that we still have
+    // something to run if the game library was never built.
+    pub fn new() -> GameCode {
+        let library_path = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(GAME_LIBRARY_NAME)))
+            .unwrap_or_else(|| PathBuf::from(GAME_LIBRARY_NAME));
+
+        let mut game_code = GameCode {
+            library_path,
+            library: std::ptr::null_mut(),
+            last_write_time: None,
+            load_count: 0,
+            update: game_update,
+            render: game_render,
+            play_sound: game_play_sound,
+        };
+
+        if let Err(e) = game_code.load() {
+            eprintln!("Using statically linked game code: {}", e);
+        }
+
+        game_code
+    }
+
+    // Called between frames. Game state lives in the platform layer, so
+    // swapping the function pointers is all a reload needs to do.
+    pub fn reload_if_changed(&mut self) {
+        let write_time = fs::metadata(&self.library_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        if write_time.is_some() && write_time != self.last_write_time {
+            match self.load() {
+                Ok(()) => eprintln!("Reloaded game code from {}", self.library_path.display()),
+                // Most likely the compiler is still writing the library.
+                // Keep running the old code and try again next frame.
+                Err(e) => eprintln!("Unable to reload game code: {}", e),
+            }
+        }
+    }
+
+    fn load(&mut self) -> Result<(), &'static str> {
+        let write_time = fs::metadata(&self.library_path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|_| "Game library not found")?;
+
+        // dlopen hands back the already loaded library for a path it has seen
+        // before, so load each build from a fresh copy. The copy is unlinked as
+        // soon as it is mapped.
+        self.load_count += 1;
+        let temp_path = env::temp_dir().join(format!(
+            "handmade_hero_{}_{}.so",
+            process::id(),
+            self.load_count
+        ));
+        fs::copy(&self.library_path, &temp_path).map_err(|_| "Unable to copy game library")?;
+        let temp_path_c = CString::new(temp_path.as_os_str().as_bytes()).unwrap();
+
+        unsafe {
+            let library = libc::dlopen(temp_path_c.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+            let _ = fs::remove_file(&temp_path);
+
+            if library.is_null() {
+                eprintln!("{}", CStr::from_ptr(libc::dlerror()).to_string_lossy());
+                return Err("Unable to open game library");
+            }
+
+            let update = dlsym(library, "game_update");
+            let render = dlsym(library, "game_render");
+            let play_sound = dlsym(library, "game_play_sound");
+            if update.is_null() || render.is_null() || play_sound.is_null() {
+                libc::dlclose(library);
+                return Err("Game library is missing entry points");
+            }
+
+            self.update = mem::transmute::<*mut c_void, UpdateFn>(update);
+            self.render = mem::transmute::<*mut c_void, RenderFn>(render);
+            self.play_sound = mem::transmute::<*mut c_void, PlaySoundFn>(play_sound);
+
+            if !self.library.is_null() {
+                libc::dlclose(self.library);
+            }
+            self.library = library;
+        }
+
+        self.last_write_time = Some(write_time);
+        Ok(())
+    }
+}
+
+impl Drop for GameCode {
+    fn drop(&mut self) {
+        if !self.library.is_null() {
+            unsafe {
+                libc::dlclose(self.library);
+            }
+        }
+    }
+}
+
+unsafe fn dlsym(library: *mut c_void, symbol: &str) -> *mut c_void {
+    let symbol = CString::new(symbol).unwrap();
+    libc::dlsym(library, symbol.as_ptr())
+}
@@ -0,0 +1,745 @@
+use std::{
+    ffi::CString,
+    fs,
+    mem::{size_of, MaybeUninit},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use handmade_hero::{Action, ButtonState, ControllerInput, GamepadButton, MAX_CONTROLLERS};
+use libc::{self, input_absinfo, input_event};
+
+use crate::bindings::{Bindings, Command};
+
+const INPUT_DIR: &str = "/dev/input";
+
+// From linux/input-event-codes.h
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const KEY_CNT: usize = 0x300;
+const ABS_CNT: usize = 0x40;
+const BTN_GAMEPAD: usize = 0x130;
+const BTN_TL2: u16 = 0x138;
+const BTN_TR2: u16 = 0x139;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+// Used when the driver does not report a flat zone for a stick
+const STICK_DEAD_ZONE: f32 = 0.1;
+
+#[derive(Clone, Copy)]
+struct AxisRange {
+    minimum: i32,
+    maximum: i32,
+    flat: i32,
+}
+
+struct Gamepad {
+    fd: OwnedFd,
+    path: PathBuf,
+    slot: usize,
+    axes: [Option<AxisRange>; ABS_CNT],
+}
+
+// Reads up to MAX_CONTROLLERS gamepads straight from evdev. Devices are
+// picked up when they appear in /dev/input and dropped when they go away.
+pub struct Gamepads {
+    inotify_fd: Option<OwnedFd>,
+    epoll_fd: Option<RawFd>,
+    gamepads: Vec<Gamepad>,
+
+    pub controllers: [ControllerInput; MAX_CONTROLLERS],
+}
+
+impl Gamepads {
+    pub fn new() -> Gamepads {
+        let mut gamepads = Gamepads {
+            inotify_fd: inotify_watch(INPUT_DIR),
+            epoll_fd: None,
+            gamepads: Vec::new(),
+            controllers: [ControllerInput::default(); MAX_CONTROLLERS],
+        };
+
+        if let Ok(entries) = fs::read_dir(INPUT_DIR) {
+            let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+            paths.sort();
+            for path in paths {
+                gamepads.connect(&path);
+            }
+        }
+
+        gamepads
+    }
+
+    // Device and hotplug fds are added to the epoll set, now and as devices
+    // are connected later on. Each is tagged with its fd.
+    pub fn register(&mut self, epoll_fd: RawFd) {
+        self.epoll_fd = Some(epoll_fd);
+
+        if let Some(inotify_fd) = &self.inotify_fd {
+            epoll_add(epoll_fd, inotify_fd.as_raw_fd());
+        }
+        for gamepad in &self.gamepads {
+            epoll_add(epoll_fd, gamepad.fd.as_raw_fd());
+        }
+    }
+
+    pub fn owns(&self, fd: RawFd) -> bool {
+        self.is_inotify_fd(fd)
+            || self
+                .gamepads
+                .iter()
+                .any(|gamepad| gamepad.fd.as_raw_fd() == fd)
+    }
+
+    // Handles whatever is waiting on `fd`, returning the platform command a
+    // bound button triggered, if any.
+    pub fn process(
+        &mut self,
+        fd: RawFd,
+        bindings: &mut Bindings,
+        actions: &mut [ButtonState; Action::COUNT],
+    ) -> Option<Command> {
+        if self.is_inotify_fd(fd) {
+            self.process_hotplug();
+            return None;
+        }
+
+        let index = self
+            .gamepads
+            .iter()
+            .position(|gamepad| gamepad.fd.as_raw_fd() == fd)?;
+
+        let mut events = [MaybeUninit::<input_event>::uninit(); 64];
+        let mut command = None;
+        loop {
+            let bytes_read = unsafe {
+                libc::read(
+                    fd,
+                    events.as_mut_ptr() as *mut libc::c_void,
+                    size_of::<[input_event; 64]>(),
+                )
+            };
+
+            if bytes_read < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() != std::io::ErrorKind::WouldBlock {
+                    // Most likely unplugged
+                    self.disconnect(index, bindings, actions);
+                }
+                break;
+            }
+
+            let num_events = bytes_read as usize / size_of::<input_event>();
+            for event in &events[..num_events] {
+                let event = unsafe { event.assume_init() };
+                let gamepad = &self.gamepads[index];
+                let controller = &mut self.controllers[gamepad.slot];
+                if let Some(triggered) =
+                    process_event(gamepad, controller, &event, bindings, actions)
+                {
+                    command = Some(triggered);
+                }
+            }
+
+            if num_events < events.len() {
+                break;
+            }
+        }
+
+        command
+    }
+
+    pub fn begin_update(&mut self) {
+        for controller in &mut self.controllers {
+            controller.begin_update();
+        }
+    }
+
+    fn is_inotify_fd(&self, fd: RawFd) -> bool {
+        matches!(&self.inotify_fd, Some(inotify_fd) if inotify_fd.as_raw_fd() == fd)
+    }
+
+    fn connect(&mut self, path: &Path) {
+        let is_event_device = path
+            .file_name()
+            .is_some_and(|name| name.as_bytes().starts_with(b"event"));
+        if !is_event_device || self.gamepads.iter().any(|gamepad| gamepad.path == path) {
+            return;
+        }
+
+        let Some(slot) = (0..MAX_CONTROLLERS).find(|&slot| !self.controllers[slot].is_connected)
+        else {
+            return;
+        };
+
+        let Some(gamepad) = gamepad_open(path, slot) else {
+            return;
+        };
+
+        eprintln!(
+            "Gamepad {} connected as controller {}",
+            path.display(),
+            slot
+        );
+        let controller = &mut self.controllers[slot];
+        *controller = ControllerInput::default();
+        controller.is_connected = true;
+        read_initial_axes(&gamepad, controller);
+
+        if let Some(epoll_fd) = self.epoll_fd {
+            epoll_add(epoll_fd, gamepad.fd.as_raw_fd());
+        }
+        self.gamepads.push(gamepad);
+    }
+
+    fn disconnect(
+        &mut self,
+        index: usize,
+        bindings: &mut Bindings,
+        actions: &mut [ButtonState; Action::COUNT],
+    ) {
+        // Closing the fd also takes it out of the epoll set
+        let gamepad = self.gamepads.swap_remove(index);
+        eprintln!("Gamepad {} disconnected", gamepad.path.display());
+
+        // Let go of anything the pad was holding down
+        let controller = &mut self.controllers[gamepad.slot];
+        for button in GamepadButton::ALL {
+            if controller.button(button).is_down() {
+                controller.buttons[button as usize].process(false);
+                bindings.process_gamepad_button(button, false, actions);
+            }
+        }
+        controller.left_stick_x = 0.0;
+        controller.left_stick_y = 0.0;
+        controller.right_stick_x = 0.0;
+        controller.right_stick_y = 0.0;
+        controller.left_trigger = 0.0;
+        controller.right_trigger = 0.0;
+        controller.is_connected = false;
+    }
+
+    fn process_hotplug(&mut self) {
+        let Some(inotify_fd) = self.inotify_fd.as_ref().map(|fd| fd.as_raw_fd()) else {
+            return;
+        };
+
+        let mut buffer = [0u8; 4096];
+        loop {
+            let bytes_read = unsafe {
+                libc::read(
+                    inotify_fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if bytes_read <= 0 {
+                break;
+            }
+
+            let mut offset = 0;
+            while offset + size_of::<libc::inotify_event>() <= bytes_read as usize {
+                let event = unsafe {
+                    (buffer.as_ptr().add(offset) as *const libc::inotify_event).read_unaligned()
+                };
+                let name_start = offset + size_of::<libc::inotify_event>();
+                let name = &buffer[name_start..name_start + event.len as usize];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                offset = name_start + event.len as usize;
+
+                // udev only hands out permissions after the device node was
+                // created, so retry on attribute changes too. Removed devices
+                // are noticed when reading from them fails.
+                if event.mask & (libc::IN_CREATE | libc::IN_ATTRIB) != 0 {
+                    let path = Path::new(INPUT_DIR).join(std::ffi::OsStr::from_bytes(name));
+                    self.connect(&path);
+                }
+            }
+        }
+    }
+}
+
+fn process_event(
+    gamepad: &Gamepad,
+    controller: &mut ControllerInput,
+    event: &input_event,
+    bindings: &mut Bindings,
+    actions: &mut [ButtonState; Action::COUNT],
+) -> Option<Command> {
+    let mut command = None;
+    let mut press = |controller: &mut ControllerInput, button: GamepadButton, is_down: bool| {
+        if controller.button(button).is_down() != is_down {
+            controller.buttons[button as usize].process(is_down);
+            if let Some(triggered) = bindings.process_gamepad_button(button, is_down, actions) {
+                command = Some(triggered);
+            }
+        }
+    };
+
+    match (event.type_, event.code) {
+        // Digital triggers on pads without analog ones
+        (EV_KEY, BTN_TL2) if gamepad.axes[ABS_Z as usize].is_none() => {
+            controller.left_trigger = if event.value != 0 { 1.0 } else { 0.0 };
+        }
+        (EV_KEY, BTN_TR2) if gamepad.axes[ABS_RZ as usize].is_none() => {
+            controller.right_trigger = if event.value != 0 { 1.0 } else { 0.0 };
+        }
+        (EV_KEY, code) => {
+            if let Some(button) = button_from_code(code) {
+                // A value of 2 is autorepeat, which still means held
+                press(controller, button, event.value != 0);
+            }
+        }
+        (EV_ABS, ABS_HAT0X) => {
+            press(controller, GamepadButton::DpadLeft, event.value < 0);
+            press(controller, GamepadButton::DpadRight, event.value > 0);
+        }
+        (EV_ABS, ABS_HAT0Y) => {
+            press(controller, GamepadButton::DpadUp, event.value < 0);
+            press(controller, GamepadButton::DpadDown, event.value > 0);
+        }
+        (EV_ABS, code) => process_axis(gamepad, controller, code, event.value),
+        _ => {}
+    }
+
+    command
+}
+
+fn process_axis(gamepad: &Gamepad, controller: &mut ControllerInput, code: u16, value: i32) {
+    let Some(range) = gamepad.axes.get(code as usize).copied().flatten() else {
+        return;
+    };
+
+    match code {
+        ABS_X => controller.left_stick_x = normalize_stick(range, value),
+        ABS_Y => controller.left_stick_y = normalize_stick(range, value),
+        ABS_RX => controller.right_stick_x = normalize_stick(range, value),
+        ABS_RY => controller.right_stick_y = normalize_stick(range, value),
+        ABS_Z => controller.left_trigger = normalize_trigger(range, value),
+        ABS_RZ => controller.right_trigger = normalize_trigger(range, value),
+        _ => {}
+    }
+}
+
+// Maps the stick to -1..1, treating the flat zone around the centre as
+// released and rescaling the rest so movement starts smoothly from zero
+fn normalize_stick(range: AxisRange, value: i32) -> f32 {
+    let half_range = (range.maximum - range.minimum) as f32 / 2.0;
+    if half_range <= 0.0 {
+        return 0.0;
+    }
+
+    let centre = (range.maximum + range.minimum) as f32 / 2.0;
+    let normalized = ((value as f32 - centre) / half_range).clamp(-1.0, 1.0);
+    let dead_zone = (range.flat as f32 / half_range).max(STICK_DEAD_ZONE);
+
+    if normalized.abs() < dead_zone {
+        0.0
+    } else {
+        normalized.signum() * (normalized.abs() - dead_zone) / (1.0 - dead_zone)
+    }
+}
+
+fn normalize_trigger(range: AxisRange, value: i32) -> f32 {
+    let full_range = (range.maximum - range.minimum) as f32;
+    if full_range <= 0.0 {
+        return 0.0;
+    }
+
+    ((value - range.minimum) as f32 / full_range).clamp(0.0, 1.0)
+}
+
+fn button_from_code(code: u16) -> Option<GamepadButton> {
+    match code {
+        0x130 => Some(GamepadButton::A),             // BTN_SOUTH
+        0x131 => Some(GamepadButton::B),             // BTN_EAST
+        0x133 => Some(GamepadButton::Y),             // BTN_NORTH
+        0x134 => Some(GamepadButton::X),             // BTN_WEST
+        0x136 => Some(GamepadButton::LeftShoulder),  // BTN_TL
+        0x137 => Some(GamepadButton::RightShoulder), // BTN_TR
+        0x13a => Some(GamepadButton::Back),          // BTN_SELECT
+        0x13b => Some(GamepadButton::Start),         // BTN_START
+        0x13c => Some(GamepadButton::Guide),         // BTN_MODE
+        0x13d => Some(GamepadButton::LeftThumb),     // BTN_THUMBL
+        0x13e => Some(GamepadButton::RightThumb),    // BTN_THUMBR
+        0x220 => Some(GamepadButton::DpadUp),        // BTN_DPAD_UP
+        0x221 => Some(GamepadButton::DpadDown),      // BTN_DPAD_DOWN
+        0x222 => Some(GamepadButton::DpadLeft),      // BTN_DPAD_LEFT
+        0x223 => Some(GamepadButton::DpadRight),     // BTN_DPAD_RIGHT
+        _ => None,
+    }
+}
+
+// Opens an evdev device if it looks like a gamepad, i.e. has BTN_GAMEPAD
+fn gamepad_open(path: &Path, slot: usize) -> Option<Gamepad> {
+    let path_c = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let fd = unsafe {
+        libc::open(
+            path_c.as_ptr(),
+            libc::O_RDONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return None;
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut key_bits = [0u8; KEY_CNT / 8];
+    let mut abs_bits = [0u8; ABS_CNT / 8];
+    unsafe {
+        if libc::ioctl(
+            fd.as_raw_fd(),
+            eviocgbit(EV_KEY, key_bits.len()),
+            key_bits.as_mut_ptr(),
+        ) < 0
+            || libc::ioctl(
+                fd.as_raw_fd(),
+                eviocgbit(EV_ABS, abs_bits.len()),
+                abs_bits.as_mut_ptr(),
+            ) < 0
+        {
+            return None;
+        }
+    }
+
+    if !test_bit(&key_bits, BTN_GAMEPAD) {
+        return None;
+    }
+
+    let mut axes = [None; ABS_CNT];
+    for (code, axis) in axes.iter_mut().enumerate() {
+        if test_bit(&abs_bits, code) {
+            *axis = absinfo_get(&fd, code).map(|info| AxisRange {
+                minimum: info.minimum,
+                maximum: info.maximum,
+                flat: info.flat,
+            });
+        }
+    }
+
+    Some(Gamepad {
+        fd,
+        path: path.to_path_buf(),
+        slot,
+        axes,
+    })
+}
+
+// Sticks that are already pushed when the pad shows up would otherwise read
+// as centred until they move
+fn read_initial_axes(gamepad: &Gamepad, controller: &mut ControllerInput) {
+    for code in [ABS_X, ABS_Y, ABS_RX, ABS_RY, ABS_Z, ABS_RZ] {
+        if gamepad.axes[code as usize].is_some() {
+            if let Some(info) = absinfo_get(&gamepad.fd, code as usize) {
+                process_axis(gamepad, controller, code, info.value);
+            }
+        }
+    }
+}
+
+fn absinfo_get(fd: &OwnedFd, code: usize) -> Option<input_absinfo> {
+    let mut info = MaybeUninit::<input_absinfo>::uninit();
+    unsafe {
+        if libc::ioctl(fd.as_raw_fd(), eviocgabs(code), info.as_mut_ptr()) < 0 {
+            None
+        } else {
+            Some(info.assume_init())
+        }
+    }
+}
+
+fn test_bit(bits: &[u8], bit: usize) -> bool {
+    bits[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+// The _IOR('E', ...) requests from linux/input.h
+fn evioc_read(nr: usize, size: usize) -> libc::c_ulong {
+    const IOC_READ: libc::c_ulong = 2;
+    (IOC_READ << 30)
+        | ((size as libc::c_ulong) << 16)
+        | ((b'E' as libc::c_ulong) << 8)
+        | nr as libc::c_ulong
+}
+
+fn eviocgbit(ev: u16, len: usize) -> libc::c_ulong {
+    evioc_read(0x20 + ev as usize, len)
+}
+
+fn eviocgabs(abs: usize) -> libc::c_ulong {
+    evioc_read(0x40 + abs, size_of::<input_absinfo>())
+}
+
+fn inotify_watch(dir: &str) -> Option<OwnedFd> {
+    let dir_c = CString::new(dir).unwrap();
+    unsafe {
+        let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+        if fd < 0 {
+            return None;
+        }
+        let fd = OwnedFd::from_raw_fd(fd);
+
+        if libc::inotify_add_watch(
+            fd.as_raw_fd(),
+            dir_c.as_ptr(),
+            libc::IN_CREATE | libc::IN_ATTRIB,
+        ) < 0
+        {
+            return None;
+        }
+        Some(fd)
+    }
+}
+
+fn epoll_add(epoll_fd: RawFd, fd: RawFd) {
+    let event = epoll::Event {
+        events: libc::EPOLLIN as u32,
+        data: fd as u64,
+    };
+    if epoll::ctl(epoll_fd, epoll::ControlOptions::EPOLL_CTL_ADD, fd, event).is_err() {
+        eprintln!("Unable to watch input device fd {}", fd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::DEFAULT_BINDINGS;
+
+    const STICK: AxisRange = AxisRange {
+        minimum: -32768,
+        maximum: 32767,
+        flat: 0,
+    };
+
+    #[test]
+    fn normalizes_sticks_to_unit_range() {
+        assert_eq!(normalize_stick(STICK, 32767), 1.0);
+        assert_eq!(normalize_stick(STICK, -32768), -1.0);
+        assert_eq!(normalize_stick(STICK, 0), 0.0);
+
+        // Half way out, past the default dead zone
+        let half = normalize_stick(STICK, 16384);
+        assert!(half > 0.4 && half < 0.5, "{}", half);
+        assert_eq!(
+            normalize_stick(STICK, -16384),
+            -normalize_stick(STICK, 16383)
+        );
+
+        // Unsigned axes centre half way up
+        let unsigned = AxisRange {
+            minimum: 0,
+            maximum: 255,
+            flat: 0,
+        };
+        assert_eq!(normalize_stick(unsigned, 0), -1.0);
+        assert_eq!(normalize_stick(unsigned, 128), 0.0);
+        assert_eq!(normalize_stick(unsigned, 255), 1.0);
+
+        let broken = AxisRange {
+            minimum: 5,
+            maximum: 5,
+            flat: 0,
+        };
+        assert_eq!(normalize_stick(broken, 5), 0.0);
+    }
+
+    #[test]
+    fn removes_the_dead_zone() {
+        // Without a flat zone from the driver the default applies
+        assert_eq!(normalize_stick(STICK, 3200), 0.0);
+        assert_eq!(normalize_stick(STICK, -3200), 0.0);
+        let just_out = normalize_stick(STICK, 3400);
+        assert!(just_out > 0.0 && just_out < 0.01, "{}", just_out);
+
+        // A larger flat zone from the driver wins
+        let flat = AxisRange {
+            flat: 8192,
+            ..STICK
+        };
+        assert_eq!(normalize_stick(flat, 8000), 0.0);
+        let just_out = normalize_stick(flat, 8400);
+        assert!(just_out > 0.0 && just_out < 0.01, "{}", just_out);
+        assert_eq!(normalize_stick(flat, 32767), 1.0);
+    }
+
+    #[test]
+    fn normalizes_triggers() {
+        let trigger = AxisRange {
+            minimum: 0,
+            maximum: 1023,
+            flat: 0,
+        };
+        assert_eq!(normalize_trigger(trigger, 0), 0.0);
+        assert_eq!(normalize_trigger(trigger, 1023), 1.0);
+        assert_eq!(normalize_trigger(trigger, 2000), 1.0);
+    }
+
+    // From linux/uinput.h
+    const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+    const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+    const EV_SYN: u16 = 0x00;
+    const BTN_SOUTH: u16 = 0x130;
+
+    // _IOW('U', ...) and _IOR('U', ...)
+    fn uinput_request(read: bool, nr: usize, size: usize) -> libc::c_ulong {
+        let direction: libc::c_ulong = if read { 2 } else { 1 };
+        (direction << 30)
+            | ((size as libc::c_ulong) << 16)
+            | ((b'U' as libc::c_ulong) << 8)
+            | nr as libc::c_ulong
+    }
+
+    fn uinput_emit(fd: &OwnedFd, type_: u16, code: u16, value: i32) {
+        let event = input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        };
+        let written = unsafe {
+            libc::write(
+                fd.as_raw_fd(),
+                &event as *const input_event as *const libc::c_void,
+                size_of::<input_event>(),
+            )
+        };
+        assert_eq!(written, size_of::<input_event>() as isize);
+    }
+
+    // Creates a virtual pad with a stick, an analog trigger, a hat and a
+    // face button, returning the uinput fd and its evdev node
+    fn uinput_pad_create() -> (OwnedFd, PathBuf) {
+        let fd = unsafe { libc::open(c"/dev/uinput".as_ptr(), libc::O_WRONLY | libc::O_NONBLOCK) };
+        assert!(fd >= 0, "Unable to open /dev/uinput");
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let int = size_of::<libc::c_int>();
+
+        unsafe {
+            libc::ioctl(
+                fd.as_raw_fd(),
+                uinput_request(false, 100, int),
+                EV_KEY as libc::c_int,
+            );
+            libc::ioctl(
+                fd.as_raw_fd(),
+                uinput_request(false, 101, int),
+                BTN_SOUTH as libc::c_int,
+            );
+            libc::ioctl(
+                fd.as_raw_fd(),
+                uinput_request(false, 100, int),
+                EV_ABS as libc::c_int,
+            );
+            for (code, minimum, maximum) in [
+                (ABS_X, -32768, 32767),
+                (ABS_RZ, 0, 1023),
+                (ABS_HAT0X, -1, 1),
+            ] {
+                libc::ioctl(
+                    fd.as_raw_fd(),
+                    uinput_request(false, 103, int),
+                    code as libc::c_int,
+                );
+                let mut setup: libc::uinput_abs_setup = std::mem::zeroed();
+                setup.code = code;
+                setup.absinfo.minimum = minimum;
+                setup.absinfo.maximum = maximum;
+                let request = uinput_request(false, 4, size_of::<libc::uinput_abs_setup>());
+                assert!(libc::ioctl(fd.as_raw_fd(), request, &setup) >= 0);
+            }
+
+            let mut setup: libc::uinput_setup = std::mem::zeroed();
+            setup.id.bustype = 0x03; // BUS_USB
+            for (dst, &src) in setup.name.iter_mut().zip(b"handmade-hero test pad") {
+                *dst = src as libc::c_char;
+            }
+            let request = uinput_request(false, 3, size_of::<libc::uinput_setup>());
+            assert!(libc::ioctl(fd.as_raw_fd(), request, &setup) >= 0);
+            assert!(libc::ioctl(fd.as_raw_fd(), UI_DEV_CREATE) >= 0);
+        }
+
+        // UI_GET_SYSNAME gives the inputN directory in sysfs, which lists
+        // the eventN node
+        let mut sysname = [0u8; 64];
+        let request = uinput_request(true, 44, sysname.len());
+        assert!(unsafe { libc::ioctl(fd.as_raw_fd(), request, sysname.as_mut_ptr()) } >= 0);
+        let sysname = &sysname[..sysname.iter().position(|&b| b == 0).unwrap()];
+        let sysfs =
+            Path::new("/sys/devices/virtual/input").join(std::ffi::OsStr::from_bytes(sysname));
+        let event = fs::read_dir(sysfs)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.file_name())
+            .find(|name| name.as_bytes().starts_with(b"event"))
+            .unwrap();
+
+        (fd, Path::new(INPUT_DIR).join(event))
+    }
+
+    // Needs write access to /dev/uinput
+    #[test]
+    #[ignore]
+    fn reads_a_virtual_pad() {
+        let (uinput, path) = uinput_pad_create();
+        let mut gamepads = Gamepads {
+            inotify_fd: None,
+            epoll_fd: None,
+            gamepads: Vec::new(),
+            controllers: [ControllerInput::default(); MAX_CONTROLLERS],
+        };
+
+        // udev needs a moment to create the node and hand out permissions
+        for _ in 0..100 {
+            gamepads.connect(&path);
+            if !gamepads.gamepads.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(gamepads.controllers[0].is_connected);
+        let fd = gamepads.gamepads[0].fd.as_raw_fd();
+
+        // Not whatever the bindings file on this machine says
+        let mut bindings = Bindings::parse(DEFAULT_BINDINGS);
+        let mut actions = [ButtonState::default(); Action::COUNT];
+        uinput_emit(&uinput, EV_KEY, BTN_SOUTH, 1);
+        uinput_emit(&uinput, EV_ABS, ABS_X, 32767);
+        uinput_emit(&uinput, EV_ABS, ABS_RZ, 1023);
+        uinput_emit(&uinput, EV_ABS, ABS_HAT0X, -1);
+        uinput_emit(&uinput, EV_SYN, 0, 0);
+        gamepads.process(fd, &mut bindings, &mut actions);
+
+        let controller = gamepads.controllers[0];
+        assert!(controller.button(GamepadButton::A).is_down());
+        assert!(controller.button(GamepadButton::DpadLeft).is_down());
+        assert!(!controller.button(GamepadButton::DpadRight).is_down());
+        assert_eq!(controller.left_stick_x, 1.0);
+        assert_eq!(controller.right_trigger, 1.0);
+
+        // A tap within one update still shows up as two transitions
+        gamepads.begin_update();
+        uinput_emit(&uinput, EV_KEY, BTN_SOUTH, 0);
+        uinput_emit(&uinput, EV_SYN, 0, 0);
+        uinput_emit(&uinput, EV_KEY, BTN_SOUTH, 1);
+        uinput_emit(&uinput, EV_SYN, 0, 0);
+        gamepads.process(fd, &mut bindings, &mut actions);
+        let a = gamepads.controllers[0].button(GamepadButton::A);
+        assert!(a.is_down());
+        assert_eq!(a.half_transition_count, 2);
+
+        unsafe { libc::ioctl(uinput.as_raw_fd(), UI_DEV_DESTROY) };
+    }
+}
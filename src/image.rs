@@ -0,0 +1,74 @@
+use png::{ColorType, Decoder, Transformations};
+
+use crate::bmp::decode_bmp;
+
+// Larger images are rejected rather than risking a huge allocation from a
+// broken header
+pub const MAX_IMAGE_SIZE: i32 = 16384;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    // Argb8888 with alpha premultiplied, top row first
+    pub pixels: Vec<u32>,
+}
+
+// Decodes a BMP or PNG file, whichever it turns out to be
+pub fn decode_image(bytes: &[u8]) -> Result<Image, &'static str> {
+    if bytes.starts_with(b"BM") {
+        decode_bmp(bytes)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(bytes)
+    } else {
+        Err("Unknown image format")
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Image, &'static str> {
+    let mut decoder = Decoder::new(bytes);
+    // Expands palettes, low bit depths and transparency chunks, and strips
+    // 16-bit channels down to 8
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|_| "Unable to read PNG header")?;
+
+    let (width, height) = reader.info().size();
+    let max = MAX_IMAGE_SIZE as u32;
+    if width == 0 || height == 0 || width > max || height > max {
+        return Err("PNG image has an unsupported size");
+    }
+
+    let mut data = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut data)
+        .map_err(|_| "Unable to decode PNG image")?;
+
+    let pixel: fn(&[u8]) -> u32 = match frame.color_type {
+        ColorType::Rgba => |p| premultiply(p[0], p[1], p[2], p[3]),
+        ColorType::Rgb => |p| premultiply(p[0], p[1], p[2], 0xff),
+        ColorType::GrayscaleAlpha => |p| premultiply(p[0], p[0], p[0], p[1]),
+        ColorType::Grayscale => |p| premultiply(p[0], p[0], p[0], 0xff),
+        ColorType::Indexed => return Err("PNG palette was not expanded"),
+    };
+
+    let bytes_per_pixel = frame.color_type.samples();
+    let pixels = data[..frame.buffer_size()]
+        .chunks_exact(frame.line_size)
+        .flat_map(|row| row[..width as usize * bytes_per_pixel].chunks_exact(bytes_per_pixel))
+        .map(pixel)
+        .collect();
+
+    Ok(Image {
+        width: width as i32,
+        height: height as i32,
+        pixels,
+    })
+}
+
+pub fn premultiply(r: u8, g: u8, b: u8, a: u8) -> u32 {
+    let scale = |c: u8| (c as u32 * a as u32 + 127) / 255;
+    (a as u32) << 24 | scale(r) << 16 | scale(g) << 8 | scale(b)
+}
@@ -0,0 +1,449 @@
+// Input handed to the game on every update. Everything in here is plain data
+// so the platform can record and replay it byte for byte. That only holds
+// without padding, so the bytes the compiler would leave out are spelled out
+// as zeroed `_pad` fields and the sizes are checked below.
+
+use std::mem::size_of;
+
+// An xkb keysym. Keys are identified by the keysym on the first shift level
+// of the active layout, so letters are always lowercase and a key released
+// after Shift was let go still matches the key that was pressed.
+pub type Keysym = u32;
+
+pub mod keysyms {
+    use super::Keysym;
+
+    pub const SPACE: Keysym = 0x0020;
+    pub const A: Keysym = 0x0061;
+    pub const D: Keysym = 0x0064;
+    pub const L: Keysym = 0x006c;
+    pub const Q: Keysym = 0x0071;
+    pub const S: Keysym = 0x0073;
+    pub const W: Keysym = 0x0077;
+    pub const BACKSPACE: Keysym = 0xff08;
+    pub const RETURN: Keysym = 0xff0d;
+    pub const ESCAPE: Keysym = 0xff1b;
+    pub const LEFT: Keysym = 0xff51;
+    pub const UP: Keysym = 0xff52;
+    pub const RIGHT: Keysym = 0xff53;
+    pub const DOWN: Keysym = 0xff54;
+    pub const F11: Keysym = 0xffc8;
+}
+
+// Keys pressed or released within a single update beyond this are dropped
+const MAX_KEYS: usize = 32;
+const MAX_TEXT_BYTES: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ButtonState {
+    // How many times the button changed between up and down since the last
+    // update. Lets the game see taps that are shorter than an update.
+    pub half_transition_count: u32,
+    pub ended_down: bool,
+    _pad: [u8; 3],
+}
+
+impl ButtonState {
+    pub fn is_down(&self) -> bool {
+        self.ended_down
+    }
+
+    // Went down at least once since the last update, even if it was let go again
+    pub fn was_pressed(&self) -> bool {
+        self.half_transition_count > 1 || (self.half_transition_count == 1 && self.ended_down)
+    }
+
+    pub fn was_released(&self) -> bool {
+        self.half_transition_count > 1 || (self.half_transition_count == 1 && !self.ended_down)
+    }
+
+    pub fn process(&mut self, is_down: bool) {
+        if self.ended_down != is_down {
+            self.ended_down = is_down;
+            self.half_transition_count += 1;
+        }
+    }
+
+    pub fn begin_update(&mut self) {
+        self.half_transition_count = 0;
+    }
+}
+
+// What the game wants to react to, independent of which keys or buttons the
+// player bound to it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    ToggleMouseLook,
+    ToggleFullscreen,
+    ToggleMaximize,
+    NextWaveform,
+}
+
+impl Action {
+    pub const COUNT: usize = 8;
+    pub const ALL: [Action; Action::COUNT] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::ToggleMouseLook,
+        Action::ToggleFullscreen,
+        Action::ToggleMaximize,
+        Action::NextWaveform,
+    ];
+
+    // Name used for the action in binding files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::ToggleMouseLook => "toggle_mouse_look",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::ToggleMaximize => "toggle_maximize",
+            Action::NextWaveform => "next_waveform",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct KeyboardInput {
+    // Every key that is held or changed state since the last update
+    keysyms: [Keysym; MAX_KEYS],
+    keys: [ButtonState; MAX_KEYS],
+    key_count: u32,
+
+    pub modifiers: Modifiers,
+    _pad: [u8; 2],
+
+    // UTF-8 text typed since the last update, with modifiers and the layout
+    // applied
+    text: [u8; MAX_TEXT_BYTES],
+    text_len: u32,
+}
+
+impl KeyboardInput {
+    pub fn new() -> Self {
+        KeyboardInput {
+            keysyms: [0; MAX_KEYS],
+            keys: [ButtonState::default(); MAX_KEYS],
+            key_count: 0,
+            modifiers: Modifiers::default(),
+            _pad: [0; 2],
+            text: [0; MAX_TEXT_BYTES],
+            text_len: 0,
+        }
+    }
+
+    pub fn key(&self, keysym: Keysym) -> ButtonState {
+        match self.find(keysym) {
+            Some(index) => self.keys[index],
+            None => ButtonState::default(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        // Only ever filled with whole characters by `push_text`
+        std::str::from_utf8(&self.text[..self.text_len as usize]).unwrap_or("")
+    }
+
+    pub fn process_key(&mut self, keysym: Keysym, is_down: bool) {
+        let index = match self.find(keysym) {
+            Some(index) => index,
+            None if (self.key_count as usize) < MAX_KEYS => {
+                let index = self.key_count as usize;
+                self.keysyms[index] = keysym;
+                self.keys[index] = ButtonState::default();
+                self.key_count += 1;
+                index
+            }
+            None => return,
+        };
+
+        self.keys[index].process(is_down);
+    }
+
+    pub fn push_text(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !c.is_control()) {
+            let start = self.text_len as usize;
+            let end = start + c.len_utf8();
+            if end > MAX_TEXT_BYTES {
+                return;
+            }
+
+            c.encode_utf8(&mut self.text[start..end]);
+            self.text_len = end as u32;
+        }
+    }
+
+    // Lets go of every held key, for when focus is lost and the key releases
+    // will go to another window
+    pub fn release_all(&mut self) {
+        for key in &mut self.keys[..self.key_count as usize] {
+            key.process(false);
+        }
+        self.modifiers = Modifiers::default();
+    }
+
+    // Called by the platform once the game has seen the input. Transitions
+    // and text are consumed, keys that are still held carry over.
+    pub fn begin_update(&mut self) {
+        let mut kept = 0;
+        for index in 0..self.key_count as usize {
+            if self.keys[index].ended_down {
+                self.keysyms[kept] = self.keysyms[index];
+                self.keys[kept] = ButtonState {
+                    half_transition_count: 0,
+                    ended_down: true,
+                    _pad: [0; 3],
+                };
+                kept += 1;
+            }
+        }
+
+        self.key_count = kept as u32;
+        self.text_len = 0;
+    }
+
+    fn find(&self, keysym: Keysym) -> Option<usize> {
+        self.keysyms[..self.key_count as usize]
+            .iter()
+            .position(|&k| k == keysym)
+    }
+}
+
+impl Default for KeyboardInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Side,
+    Extra,
+}
+
+impl MouseButton {
+    pub const COUNT: usize = 5;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MouseInput {
+    // Position is in backbuffer pixels and only meaningful while the pointer
+    // is over the window
+    pub in_window: bool,
+    _pad: [u8; 3],
+    pub x: f32,
+    pub y: f32,
+
+    // Indexed by `MouseButton`
+    pub buttons: [ButtonState; MouseButton::COUNT],
+
+    // Scrolling since the last update, positive is down and to the right.
+    // Continuous values are in surface coordinates, discrete values count
+    // wheel clicks and v120 values count 1/120ths of a click so that
+    // high-resolution wheels can scroll smoothly.
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+    pub scroll_discrete_x: i32,
+    pub scroll_discrete_y: i32,
+    pub scroll_v120_x: i32,
+    pub scroll_v120_y: i32,
+
+    // Unaccelerated motion since the last update, in the units of an
+    // unaccelerated device. Keeps coming while the pointer is locked.
+    pub delta_x: f32,
+    pub delta_y: f32,
+    // Set while the platform has the pointer locked for mouse-look
+    pub locked: bool,
+    _pad_end: [u8; 3],
+}
+
+impl MouseInput {
+    pub fn button(&self, button: MouseButton) -> ButtonState {
+        self.buttons[button as usize]
+    }
+
+    // Called by the platform once the game has seen the input
+    pub fn begin_update(&mut self) {
+        for button in &mut self.buttons {
+            button.begin_update();
+        }
+
+        self.scroll_x = 0.0;
+        self.scroll_y = 0.0;
+        self.scroll_discrete_x = 0;
+        self.scroll_discrete_y = 0;
+        self.scroll_v120_x = 0;
+        self.scroll_v120_y = 0;
+        self.delta_x = 0.0;
+        self.delta_y = 0.0;
+    }
+}
+
+// What the compositor last told us about the window
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct WindowState {
+    pub fullscreen: bool,
+    pub maximized: bool,
+    // Has keyboard focus, usually drawn with a highlighted frame
+    pub activated: bool,
+    // Snapped against other windows or a screen edge on at least one side
+    pub tiled: bool,
+    // Has keyboard focus. A seat without a keyboard never gives focus, so
+    // check `activated` as well.
+    pub focused: bool,
+    // Not visible at all, e.g. minimized or on another workspace
+    pub suspended: bool,
+    _pad: [u8; 2],
+}
+
+pub const MAX_CONTROLLERS: usize = 4;
+
+// Named after their position on an Xbox style pad, so `A` is the bottom face
+// button whatever is printed on it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    Back,
+    Start,
+    Guide,
+    LeftThumb,
+    RightThumb,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+impl GamepadButton {
+    pub const COUNT: usize = 15;
+    pub const ALL: [GamepadButton; GamepadButton::COUNT] = [
+        GamepadButton::A,
+        GamepadButton::B,
+        GamepadButton::X,
+        GamepadButton::Y,
+        GamepadButton::LeftShoulder,
+        GamepadButton::RightShoulder,
+        GamepadButton::Back,
+        GamepadButton::Start,
+        GamepadButton::Guide,
+        GamepadButton::LeftThumb,
+        GamepadButton::RightThumb,
+        GamepadButton::DpadUp,
+        GamepadButton::DpadDown,
+        GamepadButton::DpadLeft,
+        GamepadButton::DpadRight,
+    ];
+
+    // Name used for the button in binding files
+    pub fn name(self) -> &'static str {
+        match self {
+            GamepadButton::A => "a",
+            GamepadButton::B => "b",
+            GamepadButton::X => "x",
+            GamepadButton::Y => "y",
+            GamepadButton::LeftShoulder => "left_shoulder",
+            GamepadButton::RightShoulder => "right_shoulder",
+            GamepadButton::Back => "back",
+            GamepadButton::Start => "start",
+            GamepadButton::Guide => "guide",
+            GamepadButton::LeftThumb => "left_thumb",
+            GamepadButton::RightThumb => "right_thumb",
+            GamepadButton::DpadUp => "dpad_up",
+            GamepadButton::DpadDown => "dpad_down",
+            GamepadButton::DpadLeft => "dpad_left",
+            GamepadButton::DpadRight => "dpad_right",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ControllerInput {
+    pub is_connected: bool,
+    _pad: [u8; 3],
+
+    // Sticks range from -1 to 1 with the dead zone already removed, positive
+    // is right and down. Triggers range from 0 to 1.
+    pub left_stick_x: f32,
+    pub left_stick_y: f32,
+    pub right_stick_x: f32,
+    pub right_stick_y: f32,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+
+    // Indexed by `GamepadButton`
+    pub buttons: [ButtonState; GamepadButton::COUNT],
+}
+
+impl ControllerInput {
+    pub fn button(&self, button: GamepadButton) -> ButtonState {
+        self.buttons[button as usize]
+    }
+
+    // Called by the platform once the game has seen the input
+    pub fn begin_update(&mut self) {
+        for button in &mut self.buttons {
+            button.begin_update();
+        }
+    }
+}
+
+// Every byte of the recorded input has to belong to a field
+const _: () = assert!(size_of::<ButtonState>() == size_of::<u32>() + 1 + 3);
+const _: () = assert!(size_of::<Modifiers>() == 6);
+const _: () = assert!(
+    size_of::<KeyboardInput>()
+        == MAX_KEYS * (size_of::<Keysym>() + size_of::<ButtonState>())
+            + size_of::<u32>()
+            + size_of::<Modifiers>()
+            + 2
+            + MAX_TEXT_BYTES
+            + size_of::<u32>()
+);
+const _: () = assert!(
+    size_of::<MouseInput>()
+        == 1 + 3
+            + 2 * size_of::<f32>()
+            + MouseButton::COUNT * size_of::<ButtonState>()
+            + 2 * size_of::<f32>()
+            + 4 * size_of::<i32>()
+            + 2 * size_of::<f32>()
+            + 1
+            + 3
+);
+const _: () = assert!(size_of::<WindowState>() == 6 + 2);
+const _: () = assert!(
+    size_of::<ControllerInput>()
+        == 1 + 3 + 6 * size_of::<f32>() + GamepadButton::COUNT * size_of::<ButtonState>()
+);
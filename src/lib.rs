@@ -1,10 +1,76 @@
-use std::{cmp::max, f32::consts::PI};
+use std::{cmp::max, mem::size_of};
 
+mod arena;
+mod bitmap;
+mod bmp;
+mod draw;
+mod image;
+mod input;
+mod mixer;
+mod oscillator;
+mod sound;
+mod wav;
+pub use arena::Arena;
+pub use bitmap::Bitmap;
+pub use draw::BYTES_PER_PIXEL;
+pub use image::{decode_image, Image};
+pub use input::{
+    keysyms, Action, ButtonState, ControllerInput, GamepadButton, KeyboardInput, Keysym, Modifiers,
+    MouseButton, MouseInput, WindowState, MAX_CONTROLLERS,
+};
+pub use mixer::{Mixer, Source, VoiceId, MAX_VOICES};
+pub use oscillator::{Oscillator, Waveform};
+pub use sound::{SamplePlayer, Sound};
+pub use wav::{decode_wav, Wav};
+
+// Movement is tuned in units per second so it is independent of frame rate
+const SCROLL_SPEED: f32 = 1500.0;
+const PITCH_SPEED: f32 = 60.0;
+const MAX_PITCH_OFFSET: f32 = 250.0;
+// Offset units per unaccelerated pointer unit while in mouse-look
+const MOUSE_LOOK_SPEED: f32 = 1.0;
+// The tone is bent up and down from here by the pitch offset
+const BASE_TONE_HZ: f32 = 500.0;
+const TONE_AMPLITUDE: f32 = 0.7;
+// Short beep when mouse-look is switched on or off
+const BLIP_HZ: f32 = 880.0;
+const BLIP_AMPLITUDE: f32 = 0.15;
+const BLIP_SECONDS: f32 = 0.1;
+// Played when the waveform changes. Read through the platform, which knows
+// where the assets directory is.
+const SWITCH_SOUND_ASSET: &str = "switch.wav";
+// Drawn in the middle of the screen
+const HERO_ASSET: &str = "hero.png";
+
+// Game state lives at the start of permanent storage and outlives any single
+// load of the game library, so its layout must not depend on the build.
+#[repr(C)]
 pub struct Game {
-    x_offset: u8,
-    y_offset: u8,
-    pitch_offset: i32,
-    sample_index: f32, // TODO: Sync with period issues
+    x_offset: f32,
+    y_offset: f32,
+    pitch_offset: f32,
+    mixer: Mixer,
+    tone: VoiceId,
+    mouse_look: bool,
+    // The rest of permanent storage, where assets are loaded
+    assets: Arena,
+    switch_sound: Option<Sound>,
+    hero: Option<Bitmap>,
+}
+
+// Allocated once by the platform layer and handed to the game every frame.
+// Permanent storage holds the game state, transient storage is scratch space
+// the game may throw away at any time.
+#[repr(C)]
+pub struct GameMemory {
+    pub is_initialized: bool,
+    pub permanent_storage_size: usize,
+    pub permanent_storage: *mut u8,
+    pub transient_storage_size: usize,
+    pub transient_storage: *mut u8,
+    // Reads a file from the assets directory. Lives in the platform layer so
+    // it stays valid across reloads of the game library.
+    pub read_asset: fn(name: &str) -> Result<Vec<u8>, &'static str>,
 }
 
 pub struct PixelBuffer<'a> {
@@ -14,111 +80,235 @@ pub struct PixelBuffer<'a> {
     pub stride: i32,
 }
 
-pub struct SoundBuffer {
-    pub data: Vec<u8>,
+pub struct SoundBuffer<'a> {
+    pub data: &'a mut [u8],
     pub bytes_per_sample: usize,
     pub sample_rate: u32,
     pub num_channels: u8,
 }
-pub struct KeyState {
-    pub up: bool,
-    pub left: bool,
-    pub right: bool,
-    pub down: bool,
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct GameInput {
+    // Seconds of game time a single update advances by
+    pub dt: f32,
+    pub keyboard: KeyboardInput,
+    pub mouse: MouseInput,
+    pub controllers: [ControllerInput; MAX_CONTROLLERS],
+    // Bound actions, indexed by `Action`
+    pub actions: [ButtonState; Action::COUNT],
+    pub window: WindowState,
 }
 
-impl KeyState {
-    pub fn new() -> Self {
-        KeyState {
-            up: false,
-            left: false,
-            right: false,
-            down: false,
-        }
+// Recorded byte for byte, see input.rs
+const _: () = assert!(
+    size_of::<GameInput>()
+        == size_of::<f32>()
+            + size_of::<KeyboardInput>()
+            + size_of::<MouseInput>()
+            + MAX_CONTROLLERS * size_of::<ControllerInput>()
+            + Action::COUNT * size_of::<ButtonState>()
+            + size_of::<WindowState>()
+);
+
+// What the game asks of the platform layer. Written by the game during an
+// update and applied by the platform once the update is done. Requests keep
+// their value between updates, and the platform resets the window requests to
+// what the compositor actually did whenever the window state changes.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct PlatformRequests {
+    // Lock and hide the pointer and report only relative motion
+    pub mouse_look: bool,
+    pub fullscreen: bool,
+    pub maximized: bool,
+}
+
+impl GameInput {
+    pub fn action(&self, action: Action) -> ButtonState {
+        self.actions[action as usize]
     }
 }
 
 impl Game {
-    pub fn new() -> Game {
+    fn new(mut assets: Arena, read_asset: fn(&str) -> Result<Vec<u8>, &'static str>) -> Game {
+        let mut mixer = Mixer::new();
+        let tone = Oscillator::new(Waveform::Sine, BASE_TONE_HZ, TONE_AMPLITUDE);
+        let tone = mixer
+            .play(Source::Oscillator(tone), 1.0, 0.0, 0.0)
+            .expect("a new mixer should have a free voice");
+
+        let switch_sound = match read_asset(SWITCH_SOUND_ASSET)
+            .and_then(|bytes| Sound::load(&bytes, &mut assets))
+        {
+            Ok(sound) => Some(sound),
+            Err(e) => {
+                eprintln!("Unable to load {}: {}", SWITCH_SOUND_ASSET, e);
+                None
+            }
+        };
+        let hero = match read_asset(HERO_ASSET).and_then(|bytes| Bitmap::load(&bytes, &mut assets))
+        {
+            Ok(bitmap) => Some(bitmap),
+            Err(e) => {
+                eprintln!("Unable to load {}: {}", HERO_ASSET, e);
+                None
+            }
+        };
+
         Game {
-            x_offset: 0,
-            y_offset: 0,
-            pitch_offset: 0,
-            sample_index: 0.0,
+            x_offset: 0.0,
+            y_offset: 0.0,
+            pitch_offset: 0.0,
+            mixer,
+            tone,
+            mouse_look: false,
+            assets,
+            switch_sound,
+            hero,
         }
     }
 
-    pub fn update_and_render(self: &mut Self, pixel_buffer: &mut PixelBuffer, keystate: &KeyState) {
-        // Update offset on each timestep
-        if keystate.left {
-            self.x_offset = self.x_offset.wrapping_sub(25);
-            if self.pitch_offset > -250 {
-                self.pitch_offset -= 1;
+    fn from_memory(memory: &mut GameMemory) -> &mut Game {
+        assert!(size_of::<Game>() <= memory.permanent_storage_size);
+        let game = memory.permanent_storage as *mut Game;
+
+        unsafe {
+            if !memory.is_initialized {
+                // The rest of permanent storage is only ever used by the arena
+                let assets = Arena::new(
+                    memory.permanent_storage.add(size_of::<Game>()),
+                    memory.permanent_storage_size - size_of::<Game>(),
+                );
+                game.write(Game::new(assets, memory.read_asset));
+                memory.is_initialized = true;
+            }
+            &mut *game
+        }
+    }
+
+    pub fn update(self: &mut Self, input: &GameInput, requests: &mut PlatformRequests) {
+        if input.action(Action::ToggleMouseLook).was_pressed() {
+            self.mouse_look = !self.mouse_look;
+
+            // Beep on the right when switching on, on the left when off
+            let blip = Oscillator::new(Waveform::Square, BLIP_HZ, BLIP_AMPLITUDE);
+            let pan = if self.mouse_look { 0.5 } else { -0.5 };
+            if let Some(blip) = self.mixer.play(Source::Oscillator(blip), 1.0, pan, 0.0) {
+                self.mixer.stop(blip, BLIP_SECONDS);
+            }
+        }
+        requests.mouse_look = self.mouse_look;
+
+        if input.action(Action::NextWaveform).was_pressed() {
+            if let Some(Source::Oscillator(tone)) = self.mixer.source_mut(self.tone) {
+                tone.waveform = tone.waveform.next();
             }
-        } else if keystate.right {
-            self.x_offset = self.x_offset.wrapping_add(25);
-            if self.pitch_offset < 250 {
-                self.pitch_offset += 1;
+            if let Some(sound) = self.switch_sound {
+                let switch = SamplePlayer::new(sound, false);
+                self.mixer.play(Source::Sample(switch), 1.0, 0.0, 0.0);
             }
         }
 
-        if keystate.up {
-            self.y_offset = self.y_offset.wrapping_sub(25);
-            if self.pitch_offset < 250 {
-                self.pitch_offset += 1;
+        if input.action(Action::ToggleFullscreen).was_pressed() {
+            requests.fullscreen = !input.window.fullscreen;
+        }
+        if input.action(Action::ToggleMaximize).was_pressed() {
+            requests.maximized = !input.window.maximized;
+        }
+
+        // Nothing moves while the player isn't looking
+        let window = &input.window;
+        if window.suspended || !(window.focused || window.activated) {
+            return;
+        }
+
+        let mut move_x = 0.0;
+        let mut move_y = 0.0;
+        if input.action(Action::MoveLeft).is_down() {
+            move_x = -1.0;
+        } else if input.action(Action::MoveRight).is_down() {
+            move_x = 1.0;
+        }
+        if input.action(Action::MoveUp).is_down() {
+            move_y = -1.0;
+        } else if input.action(Action::MoveDown).is_down() {
+            move_y = 1.0;
+        }
+
+        // An analog stick that is pushed overrides the digital directions
+        for controller in input.controllers.iter().filter(|c| c.is_connected) {
+            if controller.left_stick_x != 0.0 {
+                move_x = controller.left_stick_x;
             }
-        } else if keystate.down {
-            self.y_offset = self.y_offset.wrapping_add(25);
-            if self.pitch_offset > -250 {
-                self.pitch_offset -= 1;
+            if controller.left_stick_y != 0.0 {
+                move_y = controller.left_stick_y;
             }
         }
 
-        self.render(pixel_buffer);
+        let scroll = SCROLL_SPEED * input.dt;
+        let pitch = PITCH_SPEED * input.dt;
+        self.x_offset = (self.x_offset + move_x * scroll).rem_euclid(256.0);
+        self.y_offset = (self.y_offset + move_y * scroll).rem_euclid(256.0);
+
+        if self.mouse_look && input.mouse.locked {
+            let mouse = &input.mouse;
+            self.x_offset = (self.x_offset + mouse.delta_x * MOUSE_LOOK_SPEED).rem_euclid(256.0);
+            self.y_offset = (self.y_offset + mouse.delta_y * MOUSE_LOOK_SPEED).rem_euclid(256.0);
+        }
+        self.pitch_offset = (self.pitch_offset + (move_x - move_y) * pitch)
+            .clamp(-MAX_PITCH_OFFSET, MAX_PITCH_OFFSET);
     }
 
-    fn render(self: &mut Self, pixel_buffer: &mut PixelBuffer) {
+    pub fn render(self: &mut Self, pixel_buffer: &mut PixelBuffer) {
+        let x_offset = self.x_offset as u8;
+        let y_offset = self.y_offset as u8;
         let height = pixel_buffer.height;
         let width = pixel_buffer.width;
-        let stride = pixel_buffer.stride;
-        let bytes_per_pixel = pixel_buffer.stride / pixel_buffer.width;
 
         for y in 0..height {
-            for x in 0..width {
-                let pixel = (y * stride + x * bytes_per_pixel) as usize;
-                pixel_buffer.data[pixel] = self // B
-                    .x_offset
-                    .wrapping_add(x as u8);
-                pixel_buffer.data[pixel + 1] = self.y_offset.wrapping_add(y as u8);
-                pixel_buffer.data[pixel + 2] = 0x00; // R
+            let row = pixel_buffer.row_mut(y);
+            for (x, pixel) in row.chunks_exact_mut(BYTES_PER_PIXEL).enumerate() {
+                pixel[0] = x_offset.wrapping_add(x as u8); // B
+                pixel[1] = y_offset.wrapping_add(y as u8);
+                pixel[2] = 0x00; // R
             }
         }
+
+        if let Some(hero) = &self.hero {
+            let x = (width - hero.width) / 2;
+            let y = (height - hero.height) / 2;
+            pixel_buffer.draw_bitmap(hero, x, y);
+        }
     }
 
     pub fn play_sound(self: &mut Self, sound_buffer: &mut SoundBuffer) {
-        let tone_hz = 500.0 + self.pitch_offset as f32;
-        let amplitude = 0.7;
-        let length = sound_buffer.data.len();
-        let channels = sound_buffer.num_channels;
-        let sample_rate_f = sound_buffer.sample_rate as f32;
-
-        // y = sin(kt)
-        let k = 2.0 * tone_hz * PI;
-        let mut t = self.sample_index / sample_rate_f;
-        let mut y = amplitude * f32::sin(k * t);
-
-        let mut i = 0;
-        while i < length {
-            let y_bytes = y.to_ne_bytes();
-            for _channel in 0..channels {
-                for b in y_bytes {
-                    sound_buffer.data[i] = b;
-                    i += 1;
-                }
-            }
-            self.sample_index += 1.0;
-            t = self.sample_index / sample_rate_f;
-            y = amplitude * f32::sin(k * t);
+        if let Some(Source::Oscillator(tone)) = self.mixer.source_mut(self.tone) {
+            tone.set_frequency(BASE_TONE_HZ + self.pitch_offset);
         }
+
+        self.mixer.mix(sound_buffer);
     }
 }
+
+// Entry points looked up by name when the platform layer loads this crate as a
+// cdylib. They use the Rust ABI, so the platform and the game library have to
+// be built by the same compiler.
+pub type UpdateFn = fn(&mut GameMemory, &GameInput, &mut PlatformRequests);
+pub type RenderFn = fn(&mut GameMemory, &mut PixelBuffer);
+pub type PlaySoundFn = fn(&mut GameMemory, &mut SoundBuffer);
+
+#[no_mangle]
+pub fn game_update(memory: &mut GameMemory, input: &GameInput, requests: &mut PlatformRequests) {
+    Game::from_memory(memory).update(input, requests);
+}
+
+#[no_mangle]
+pub fn game_render(memory: &mut GameMemory, pixel_buffer: &mut PixelBuffer) {
+    Game::from_memory(memory).render(pixel_buffer);
+}
+
+#[no_mangle]
+pub fn game_play_sound(memory: &mut GameMemory, sound_buffer: &mut SoundBuffer) {
+    Game::from_memory(memory).play_sound(sound_buffer);
+}
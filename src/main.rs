@@ -1,25 +1,57 @@
-use handmade_hero::{self, KeyState, PixelBuffer};
+use handmade_hero::{
+    self, Action, ButtonState, GameInput, KeyboardInput, Keysym, Modifiers, MouseButton,
+    MouseInput, PixelBuffer, PlatformRequests, WindowState, BYTES_PER_PIXEL,
+};
+mod bindings;
+mod decorations;
+mod game_code;
+mod gamepad;
+mod memory;
 mod pulseaudio;
+mod recording;
+mod scale;
 mod shm;
+mod swapchain;
 
+use bindings::{Bindings, Command};
+use decorations::{draw_title_bar, hit_test, Hit, TITLE_BAR_HEIGHT};
 use epoll;
-use pulseaudio::pulse_init;
+use game_code::GameCode;
+use gamepad::Gamepads;
+use memory::{allocate_platform_memory, PlatformMemory};
+use pulseaudio::{pulse_attach_epoll, pulse_init, pulse_wait};
+use recording::LoopRecorder;
+use scale::scale_nearest;
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
+    env,
     fs::File,
-    os::fd::{AsFd, AsRawFd, OwnedFd},
+    os::fd::{AsFd, AsRawFd, OwnedFd, RawFd},
     rc::Rc,
 };
+use swapchain::Swapchain;
 use wayland_client::{
     protocol::{
-        __interfaces, wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_pointer, wl_registry,
-        wl_seat, wl_shm, wl_shm_pool, wl_surface,
+        __interfaces, wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_output, wl_pointer,
+        wl_registry, wl_seat, wl_shm, wl_shm_pool, wl_surface,
     },
-    Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
 };
+use wayland_cursor::CursorTheme;
 use wayland_protocols::{
+    wp,
+    wp::{
+        cursor_shape::v1::client::{wp_cursor_shape_device_v1, wp_cursor_shape_manager_v1},
+        fractional_scale::v1::client::{wp_fractional_scale_manager_v1, wp_fractional_scale_v1},
+        pointer_constraints::zv1::client::{zwp_locked_pointer_v1, zwp_pointer_constraints_v1},
+        relative_pointer::zv1::client::{zwp_relative_pointer_manager_v1, zwp_relative_pointer_v1},
+        viewporter::client::{wp_viewport, wp_viewporter},
+    },
     xdg,
-    xdg::shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base},
+    xdg::{
+        decoration::zv1::client::{zxdg_decoration_manager_v1, zxdg_toplevel_decoration_v1},
+        shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base},
+    },
 };
 use xkbcommon::{
     xkb,
@@ -29,17 +61,20 @@ use xkbcommon::{
     },
 };
 
-impl Dispatch<wl_buffer::WlBuffer, ()> for WaylandState {
+impl Dispatch<wl_buffer::WlBuffer, usize> for WaylandState {
     fn event(
-        _state: &mut Self,
-        buffer: &wl_buffer::WlBuffer,
+        state: &mut Self,
+        _buffer: &wl_buffer::WlBuffer,
         event: wl_buffer::Event,
-        _udata: &(),
+        index: &usize,
         _conn: &Connection,
         _qh: &QueueHandle<WaylandState>,
     ) {
+        // The compositor is done reading the buffer, it can be drawn into again
         if let wl_buffer::Event::Release = event {
-            buffer.destroy();
+            if let Some(swapchain) = state.swapchain.as_mut() {
+                swapchain.release(*index);
+            }
         }
     }
 }
@@ -77,9 +112,13 @@ impl Dispatch<wl_callback::WlCallback, u32> for WaylandState {
             callback_data: time,
         } = event
         {
-            let delta_t: u64 = (time - *prevtime).into();
+            let delta_t = time.wrapping_sub(*prevtime);
             eprint!("ms per frame: {}\r", delta_t);
-            wl_frame_draw(state, &qh);
+            wl_frame_update(state, qh, delta_t);
+            // Nobody would see it
+            if !state.window.suspended {
+                wl_frame_draw(state, &qh);
+            }
             state.surface.as_ref().unwrap().frame(&qh, time);
             state.surface.as_ref().unwrap().commit();
         }
@@ -109,6 +148,18 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
         _qh: &QueueHandle<WaylandState>,
     ) {
         match event {
+            wl_keyboard::Event::Enter { .. } => {
+                state.window.focused = true;
+                volume_update(state);
+            }
+            wl_keyboard::Event::Leave { .. } => {
+                // We won't hear about keys released from now on, let go of
+                // everything so nothing stays stuck
+                state.keyboard.release_all();
+                state.bindings.release_keys(&mut state.actions);
+                state.window.focused = false;
+                volume_update(state);
+            }
             wl_keyboard::Event::Keymap {
                 format,
                 fd,
@@ -125,7 +176,8 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
                 mods_locked,
                 group,
             } => {
-                state.xkb_state.as_mut().unwrap().update_mask(
+                let xkb_state = state.xkb_state.as_mut().unwrap();
+                xkb_state.update_mask(
                     mods_depressed,
                     mods_latched,
                     mods_locked,
@@ -133,6 +185,7 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
                     group,
                     group,
                 );
+                state.keyboard.modifiers = xkb_modifiers_get(xkb_state);
             }
 
             wl_keyboard::Event::Key {
@@ -141,25 +194,25 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
                 key,
                 state: key_state,
             } => {
-                let key_sym_name = xkb_keysym_get(state.xkb_state.as_ref().unwrap(), key);
-                dbg!(&key_sym_name);
-                match key_state {
-                    WEnum::Value(wl_keyboard::KeyState::Pressed) => match key_sym_name.as_str() {
-                        "q" => state.running = false,
-                        "w" => state.keystate.up = true,
-                        "a" => state.keystate.left = true,
-                        "s" => state.keystate.down = true,
-                        "d" => state.keystate.right = true,
-                        &_ => {}
-                    },
-                    WEnum::Value(wl_keyboard::KeyState::Released) => match key_sym_name.as_str() {
-                        "w" => state.keystate.up = false,
-                        "a" => state.keystate.left = false,
-                        "s" => state.keystate.down = false,
-                        "d" => state.keystate.right = false,
-                        &_ => {}
-                    },
-                    _ => {} // close match key_state
+                let is_down = match key_state {
+                    WEnum::Value(wl_keyboard::KeyState::Pressed) => true,
+                    WEnum::Value(wl_keyboard::KeyState::Released) => false,
+                    _ => return,
+                };
+
+                let keysym = xkb_keysym_get(state, key);
+                state.keyboard.process_key(keysym, is_down);
+
+                if is_down {
+                    let text = xkb_text_get(state.xkb_state.as_ref().unwrap(), key);
+                    state.keyboard.push_text(&text);
+                }
+
+                let command = state
+                    .bindings
+                    .process_key(keysym, key, is_down, &mut state.actions);
+                if let Some(command) = command {
+                    platform_command_run(state, command);
                 }
             }
             _ => {} // close match event
@@ -167,6 +220,28 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
     }
 }
 
+// Ducks the game's audio while in the background and mutes it while hidden
+fn volume_update(state: &mut WaylandState) {
+    let window = state.window;
+    let volume = if window.suspended {
+        0.0
+    } else if window.focused || window.activated {
+        1.0
+    } else {
+        BACKGROUND_VOLUME
+    };
+
+    state.volume.set(volume);
+}
+
+fn platform_command_run(state: &mut WaylandState, command: Command) {
+    match command {
+        Command::Quit => state.running = false,
+        Command::ToggleLoopRecording => state.recorder.toggle(&mut state.memory.borrow_mut()),
+        Command::Game(_) => {} // forwarded to the game through GameInput::actions
+    }
+}
+
 fn xkb_configure(state: &mut WaylandState, fd: OwnedFd) {
     let xkb_context = xkb::Context::new(XKB_CONTEXT_NO_FLAGS);
 
@@ -196,21 +271,211 @@ fn xkb_configure(state: &mut WaylandState, fd: OwnedFd) {
     state.xkb_keymap = Some(xkb_keymap);
 }
 
-fn xkb_keysym_get(xkb_state: &xkb::State, keycode: u32) -> String {
+// Keys are reported by their unshifted keysym so that a press and its release
+// agree even if modifiers changed in between.
+fn xkb_keysym_get(state: &WaylandState, keycode: u32) -> Keysym {
+    let xkb_keycode = (keycode + 8).into();
+    let layout = state
+        .xkb_state
+        .as_ref()
+        .unwrap()
+        .key_get_layout(xkb_keycode);
+    state
+        .xkb_keymap
+        .as_ref()
+        .unwrap()
+        .key_get_syms_by_level(xkb_keycode, layout, 0)
+        .first()
+        .map_or(0, |keysym| keysym.raw())
+}
+
+fn xkb_text_get(xkb_state: &xkb::State, keycode: u32) -> String {
     let xkb_keycode = keycode + 8;
     xkb_state.key_get_utf8(xkb_keycode.into())
 }
 
+fn xkb_modifiers_get(xkb_state: &xkb::State) -> Modifiers {
+    let is_active = |name| xkb_state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE);
+    Modifiers {
+        shift: is_active(xkb::MOD_NAME_SHIFT),
+        ctrl: is_active(xkb::MOD_NAME_CTRL),
+        alt: is_active(xkb::MOD_NAME_ALT),
+        logo: is_active(xkb::MOD_NAME_LOGO),
+        caps_lock: is_active(xkb::MOD_NAME_CAPS),
+        num_lock: is_active(xkb::MOD_NAME_NUM),
+    }
+}
+
 impl Dispatch<wl_pointer::WlPointer, ()> for WaylandState {
     fn event(
-        _state: &mut Self,
+        state: &mut Self,
         _pointer: &wl_pointer::WlPointer,
-        _event: wl_pointer::Event,
+        event: wl_pointer::Event,
         _udata: &(),
         _conn: &Connection,
         _qh: &QueueHandle<WaylandState>,
     ) {
-        // no events needed yet
+        match event {
+            wl_pointer::Event::Enter {
+                surface_x,
+                surface_y,
+                ..
+            }
+            | wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => state.pointer_position = (surface_x, surface_y),
+            wl_pointer::Event::Button {
+                serial,
+                button: 0x110, // BTN_LEFT
+                state: WEnum::Value(wl_pointer::ButtonState::Pressed),
+                ..
+            } if decoration_press(state, serial) => {
+                // Clicks on our own title bar and frame never reach the game
+                return;
+            }
+            _ => {}
+        }
+
+        // The game sees the pointer in backbuffer pixels, relative to the part
+        // of the window below the title bar
+        let title_bar = title_bar_height(state);
+        let scale_x = GAME_WIDTH as f32 / state.width as f32;
+        let scale_y = GAME_HEIGHT as f32 / (state.height - title_bar).max(1) as f32;
+        let mouse = &mut state.mouse;
+        match event {
+            wl_pointer::Event::Enter {
+                serial,
+                surface: _,
+                surface_x,
+                surface_y,
+            } => {
+                mouse.in_window = true;
+                mouse.x = surface_x as f32 * scale_x;
+                mouse.y = (surface_y as f32 - title_bar as f32) * scale_y;
+
+                // Needed to change the cursor while the pointer is over us
+                state.pointer_serial = serial;
+                pointer_cursor_update(state, serial);
+            }
+            wl_pointer::Event::Leave {
+                serial: _,
+                surface: _,
+            } => {
+                // Buttons held while leaving are released outside our window
+                mouse.in_window = false;
+                for button in &mut mouse.buttons {
+                    button.process(false);
+                }
+            }
+            wl_pointer::Event::Motion {
+                time: _,
+                surface_x,
+                surface_y,
+            } => {
+                mouse.x = surface_x as f32 * scale_x;
+                mouse.y = (surface_y as f32 - title_bar as f32) * scale_y;
+            }
+            wl_pointer::Event::Button {
+                serial: _,
+                time: _,
+                button,
+                state: button_state,
+            } => {
+                let is_down = match button_state {
+                    WEnum::Value(wl_pointer::ButtonState::Pressed) => true,
+                    WEnum::Value(wl_pointer::ButtonState::Released) => false,
+                    _ => return,
+                };
+
+                if let Some(button) = pointer_button_get(button) {
+                    mouse.buttons[button as usize].process(is_down);
+                }
+            }
+            wl_pointer::Event::Axis {
+                time: _,
+                axis,
+                value,
+            } => match axis {
+                WEnum::Value(wl_pointer::Axis::VerticalScroll) => mouse.scroll_y += value as f32,
+                WEnum::Value(wl_pointer::Axis::HorizontalScroll) => mouse.scroll_x += value as f32,
+                _ => {}
+            },
+            wl_pointer::Event::AxisDiscrete { axis, discrete } => match axis {
+                WEnum::Value(wl_pointer::Axis::VerticalScroll) => {
+                    mouse.scroll_discrete_y += discrete
+                }
+                WEnum::Value(wl_pointer::Axis::HorizontalScroll) => {
+                    mouse.scroll_discrete_x += discrete
+                }
+                _ => {}
+            },
+            wl_pointer::Event::AxisValue120 { axis, value120 } => {
+                // Replaces axis_discrete from wl_pointer version 8 on, so
+                // keep the click count filled in as well
+                let (v120, discrete) = match axis {
+                    WEnum::Value(wl_pointer::Axis::VerticalScroll) => {
+                        (&mut mouse.scroll_v120_y, &mut mouse.scroll_discrete_y)
+                    }
+                    WEnum::Value(wl_pointer::Axis::HorizontalScroll) => {
+                        (&mut mouse.scroll_v120_x, &mut mouse.scroll_discrete_x)
+                    }
+                    _ => return,
+                };
+
+                let clicks_before = *v120 / 120;
+                *v120 += value120;
+                *discrete += *v120 / 120 - clicks_before;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Height of the client-side title bar, zero when there is none
+fn title_bar_height(state: &WaylandState) -> i32 {
+    if state.client_side_decorations && !state.window.fullscreen {
+        TITLE_BAR_HEIGHT
+    } else {
+        0
+    }
+}
+
+// Handles a left click on the client-side decorations. Returns false if the
+// click was meant for the game.
+fn decoration_press(state: &mut WaylandState, serial: u32) -> bool {
+    if title_bar_height(state) == 0 {
+        return false;
+    }
+
+    let window = state.window;
+    let resizable = !(window.maximized || window.fullscreen || window.tiled);
+    let (x, y) = state.pointer_position;
+    let toplevel = state.xdg_toplevel.as_ref().unwrap();
+    let seat = state.seat.as_ref().unwrap();
+
+    match hit_test(x, y, state.width, state.height, resizable) {
+        Hit::Content => return false,
+        Hit::TitleBar => toplevel._move(seat, serial),
+        Hit::Resize(edge) => toplevel.resize(seat, serial, edge),
+        Hit::Close => state.running = false,
+        Hit::Maximize if window.maximized => toplevel.unset_maximized(),
+        Hit::Maximize => toplevel.set_maximized(),
+    }
+
+    true
+}
+
+// Linux input event codes for mouse buttons
+fn pointer_button_get(button: u32) -> Option<MouseButton> {
+    match button {
+        0x110 => Some(MouseButton::Left),   // BTN_LEFT
+        0x111 => Some(MouseButton::Right),  // BTN_RIGHT
+        0x112 => Some(MouseButton::Middle), // BTN_MIDDLE
+        0x113 => Some(MouseButton::Side),   // BTN_SIDE
+        0x114 => Some(MouseButton::Extra),  // BTN_EXTRA
+        _ => None,
     }
 }
 
@@ -241,6 +506,45 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
                 state.compositor = Some(registry.bind(name, version, qh, *udata))
             } else if interface == xdg::shell::client::__interfaces::XDG_WM_BASE_INTERFACE.name {
                 state.xdg_wm_base = Some(registry.bind(name, version, qh, *udata));
+            } else if interface
+                == wp::relative_pointer::zv1::client::__interfaces::ZWP_RELATIVE_POINTER_MANAGER_V1_INTERFACE.name
+            {
+                state.relative_pointer_manager = Some(registry.bind(name, 1, qh, ()));
+            } else if interface
+                == wp::pointer_constraints::zv1::client::__interfaces::ZWP_POINTER_CONSTRAINTS_V1_INTERFACE.name
+            {
+                state.pointer_constraints = Some(registry.bind(name, 1, qh, ()));
+            } else if interface == wp::viewporter::client::__interfaces::WP_VIEWPORTER_INTERFACE.name
+            {
+                state.viewporter = Some(registry.bind(name, 1, qh, ()));
+            } else if interface
+                == wp::cursor_shape::v1::client::__interfaces::WP_CURSOR_SHAPE_MANAGER_V1_INTERFACE.name
+            {
+                state.cursor_shape_manager = Some(registry.bind(name, 1, qh, ()));
+            } else if interface
+                == wp::fractional_scale::v1::client::__interfaces::WP_FRACTIONAL_SCALE_MANAGER_V1_INTERFACE.name
+            {
+                state.fractional_scale_manager = Some(registry.bind(name, 1, qh, ()));
+            } else if interface
+                == xdg::decoration::zv1::client::__interfaces::ZXDG_DECORATION_MANAGER_V1_INTERFACE.name
+            {
+                state.decoration_manager = Some(registry.bind(name, 1, qh, ()));
+            } else if interface == __interfaces::WL_OUTPUT_INTERFACE.name {
+                // Remember the global name so the output can be found when it goes away
+                state.outputs.push(Output {
+                    global_name: name,
+                    output: registry.bind(name, version.min(4), qh, name),
+                    scale: 1,
+                    entered: false,
+                });
+            }
+        } else if let wl_registry::Event::GlobalRemove { name } = event {
+            if let Some(index) = state.outputs.iter().position(|o| o.global_name == name) {
+                let output = state.outputs.remove(index);
+                if output.output.version() >= 3 {
+                    output.output.release();
+                }
+                wl_scale_update(state);
             }
         }
     }
@@ -248,7 +552,7 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
 
 impl Dispatch<wl_seat::WlSeat, ()> for WaylandState {
     fn event(
-        _state: &mut Self,
+        state: &mut Self,
         seat: &wl_seat::WlSeat,
         event: wl_seat::Event,
         _udata: &(),
@@ -263,7 +567,15 @@ impl Dispatch<wl_seat::WlSeat, ()> for WaylandState {
                 }
 
                 if capability.intersects(wl_seat::Capability::Pointer) {
-                    seat.get_pointer(qh, ());
+                    let pointer = seat.get_pointer(qh, ());
+                    if let Some(manager) = &state.relative_pointer_manager {
+                        state.relative_pointer =
+                            Some(manager.get_relative_pointer(&pointer, qh, ()));
+                    }
+                    if let Some(manager) = &state.cursor_shape_manager {
+                        state.cursor_shape_device = Some(manager.get_pointer(&pointer, qh, ()));
+                    }
+                    state.pointer = Some(pointer);
                 }
             }
         }
@@ -298,15 +610,94 @@ impl Dispatch<wl_shm::WlShm, ()> for WaylandState {
 }
 
 impl Dispatch<wl_surface::WlSurface, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _surface: &wl_surface::WlSurface,
+        event: wl_surface::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            wl_surface::Event::Enter { ref output } | wl_surface::Event::Leave { ref output } => {
+                let entered = matches!(event, wl_surface::Event::Enter { .. });
+                if let Some(output) = state.outputs.iter_mut().find(|o| o.output == *output) {
+                    output.entered = entered;
+                }
+            }
+            wl_surface::Event::PreferredBufferScale { factor } => {
+                state.preferred_buffer_scale = Some(factor);
+            }
+            _ => return,
+        }
+
+        wl_scale_update(state);
+    }
+}
+
+// Tells the cursor surface apart from the window's, its events don't matter
+struct CursorSurface;
+
+impl Dispatch<wl_surface::WlSurface, CursorSurface> for WaylandState {
     fn event(
         _state: &mut Self,
-        _shm: &wl_surface::WlSurface,
+        _surface: &wl_surface::WlSurface,
         _event: wl_surface::Event,
+        _udata: &CursorSurface,
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, u32> for WaylandState {
+    fn event(
+        state: &mut Self,
+        output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _global_name: &u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            wl_output::Event::Scale { factor } => {
+                if let Some(output) = state.outputs.iter_mut().find(|o| o.output == *output) {
+                    output.scale = factor;
+                }
+            }
+            // Sent once all properties of the output have been updated
+            wl_output::Event::Done => wl_scale_update(state),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _manager: &wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        _event: wp_fractional_scale_manager_v1::Event,
         _udata: &(),
         _conn: &Connection,
         _qh: &QueueHandle<WaylandState>,
     ) {
-        // No events needed yet
+        // wp_fractional_scale_manager_v1 has no events
+    }
+}
+
+impl Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _fractional_scale: &wp_fractional_scale_v1::WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            state.fractional_scale_120 = Some(scale);
+            wl_scale_update(state);
+        }
     }
 }
 
@@ -323,6 +714,13 @@ impl Dispatch<xdg_surface::XdgSurface, ()> for WaylandState {
             eprintln!("received xdg_surface_configure");
             surface.ack_configure(serial);
 
+            // Apply the size from the toplevel configure that came before this
+            if let Some((width, height)) = state.pending_size.take() {
+                state.width = width;
+                state.height = height;
+                wl_surface_layout(state);
+            }
+
             wl_frame_draw(state, &qh);
             state.surface.as_ref().unwrap().commit();
         }
@@ -345,17 +743,87 @@ impl Dispatch<xdg_toplevel::XdgToplevel, ()> for WaylandState {
             xdg_toplevel::Event::Configure {
                 width,
                 height,
-                states: _,
+                states,
             } => {
                 if width != 0 && height != 0 {
-                    // width = height = 0 means we get to decide the size
-                    state.width = width;
-                    state.height = height;
+                    // width = height = 0 means we get to decide the size.
+                    // Applied once the xdg_surface configure ends the sequence.
+                    state.pending_size = Some((width, height));
                 }
+
+                // Keyboard focus comes from wl_keyboard, not the configure
+                let focused = state.window.focused;
+                state.window = xdg_window_state_get(&states);
+                state.window.focused = focused;
+                volume_update(state);
+
+                // Whatever the game asked for, this is what the window is now
+                state.requests.fullscreen = state.window.fullscreen;
+                state.requests.maximized = state.window.maximized;
+                state.applied_requests.fullscreen = state.window.fullscreen;
+                state.applied_requests.maximized = state.window.maximized;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zxdg_decoration_manager_v1::ZxdgDecorationManagerV1, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _manager: &zxdg_decoration_manager_v1::ZxdgDecorationManagerV1,
+        _event: zxdg_decoration_manager_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // zxdg_decoration_manager_v1 has no events
+    }
+}
+
+impl Dispatch<zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _decoration: &zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1,
+        event: zxdg_toplevel_decoration_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // The compositor may draw the frame itself or leave it to us,
+        // whatever we asked for
+        if let zxdg_toplevel_decoration_v1::Event::Configure { mode } = event {
+            let client_side = mode != WEnum::Value(zxdg_toplevel_decoration_v1::Mode::ServerSide);
+            if client_side != state.client_side_decorations {
+                state.client_side_decorations = client_side;
+                wl_surface_layout(state);
             }
+        }
+    }
+}
+
+// `states` is an array of xdg_toplevel::State values in native byte order
+fn xdg_window_state_get(states: &[u8]) -> WindowState {
+    let mut window = WindowState::default();
+
+    for state in states.chunks_exact(4) {
+        let state = u32::from_ne_bytes(state.try_into().unwrap());
+        match xdg_toplevel::State::try_from(state) {
+            Ok(xdg_toplevel::State::Fullscreen) => window.fullscreen = true,
+            Ok(xdg_toplevel::State::Maximized) => window.maximized = true,
+            Ok(xdg_toplevel::State::Activated) => window.activated = true,
+            Ok(xdg_toplevel::State::Suspended) => window.suspended = true,
+            Ok(
+                xdg_toplevel::State::TiledLeft
+                | xdg_toplevel::State::TiledRight
+                | xdg_toplevel::State::TiledTop
+                | xdg_toplevel::State::TiledBottom,
+            ) => window.tiled = true,
             _ => {}
         }
     }
+
+    window
 }
 
 impl Dispatch<xdg_wm_base::XdgWmBase, ()> for WaylandState {
@@ -374,6 +842,128 @@ impl Dispatch<xdg_wm_base::XdgWmBase, ()> for WaylandState {
     }
 }
 
+impl Dispatch<wp_viewporter::WpViewporter, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _viewporter: &wp_viewporter::WpViewporter,
+        _event: wp_viewporter::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // wp_viewporter has no events
+    }
+}
+
+impl Dispatch<wp_viewport::WpViewport, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _viewport: &wp_viewport::WpViewport,
+        _event: wp_viewport::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // wp_viewport has no events
+    }
+}
+
+impl Dispatch<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _manager: &wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+        _event: wp_cursor_shape_manager_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // wp_cursor_shape_manager_v1 has no events
+    }
+}
+
+impl Dispatch<wp_cursor_shape_device_v1::WpCursorShapeDeviceV1, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _device: &wp_cursor_shape_device_v1::WpCursorShapeDeviceV1,
+        _event: wp_cursor_shape_device_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // wp_cursor_shape_device_v1 has no events
+    }
+}
+
+impl Dispatch<zwp_locked_pointer_v1::ZwpLockedPointerV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _locked_pointer: &zwp_locked_pointer_v1::ZwpLockedPointerV1,
+        event: zwp_locked_pointer_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        match event {
+            zwp_locked_pointer_v1::Event::Locked => {
+                state.mouse.locked = true;
+                pointer_cursor_update(state, state.pointer_serial);
+            }
+            zwp_locked_pointer_v1::Event::Unlocked => {
+                // The lock comes back by itself when we regain focus
+                state.mouse.locked = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _constraints: &zwp_pointer_constraints_v1::ZwpPointerConstraintsV1,
+        _event: zwp_pointer_constraints_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // zwp_pointer_constraints_v1 has no events
+    }
+}
+
+impl Dispatch<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1, ()> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _manager: &zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1,
+        _event: zwp_relative_pointer_manager_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        // zwp_relative_pointer_manager_v1 has no events
+    }
+}
+
+impl Dispatch<zwp_relative_pointer_v1::ZwpRelativePointerV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _relative_pointer: &zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        event: zwp_relative_pointer_v1::Event,
+        _udata: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<WaylandState>,
+    ) {
+        if let zwp_relative_pointer_v1::Event::RelativeMotion {
+            dx_unaccel,
+            dy_unaccel,
+            ..
+        } = event
+        {
+            state.mouse.delta_x += dx_unaccel as f32;
+            state.mouse.delta_y += dy_unaccel as f32;
+        }
+    }
+}
+
 struct WaylandState {
     // Wayland
     shm: Option<wl_shm::WlShm>,
@@ -381,27 +971,88 @@ struct WaylandState {
     compositor: Option<wl_compositor::WlCompositor>,
     xdg_wm_base: Option<xdg_wm_base::XdgWmBase>,
     surface: Option<wl_surface::WlSurface>,
+    pointer: Option<wl_pointer::WlPointer>,
+    pointer_serial: u32,
+
+    // Pointer lock
+    relative_pointer_manager: Option<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1>,
+    relative_pointer: Option<zwp_relative_pointer_v1::ZwpRelativePointerV1>,
+    pointer_constraints: Option<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1>,
+    locked_pointer: Option<zwp_locked_pointer_v1::ZwpLockedPointerV1>,
+    // Bring back the default cursor once the lock is gone. The theme and
+    // its surface are only loaded without cursor-shape-v1.
+    cursor_shape_manager: Option<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+    cursor_shape_device: Option<wp_cursor_shape_device_v1::WpCursorShapeDeviceV1>,
+    cursor_theme: Option<CursorTheme>,
+    cursor_surface: Option<wl_surface::WlSurface>,
 
     // Xdg
     xdg_surface: Option<xdg_surface::XdgSurface>,
     xdg_toplevel: Option<xdg_toplevel::XdgToplevel>,
+    window: WindowState,
 
-    // Backbuffer
-    data: Option<memmap::MmapMut>,
+    // Decorations. Drawn by us above the game when the compositor has no
+    // decoration manager or refuses to draw them.
+    decoration_manager: Option<zxdg_decoration_manager_v1::ZxdgDecorationManagerV1>,
+    decoration: Option<zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1>,
+    client_side_decorations: bool,
+    // Last pointer position in surface coordinates
+    pointer_position: (f64, f64),
+
+    // Backbuffer. The game always draws GAME_WIDTH x GAME_HEIGHT pixels into
+    // `backbuffer` which `scale_nearest` stretches over the window's physical
+    // pixels. At 1x with a viewporter the game draws straight into the
+    // swapchain and the compositor does the stretching.
+    swapchain: Option<Swapchain>,
+    viewporter: Option<wp_viewporter::WpViewporter>,
+    viewport: Option<wp_viewport::WpViewport>,
+    backbuffer: Vec<u8>,
+    // Window size in surface coordinates
     width: i32,
     height: i32,
-    bytes_per_pixel: i32,
-    pool: Option<wl_shm_pool::WlShmPool>,
+    pending_size: Option<(i32, i32)>,
+
+    // Output scale, in 120ths like wp_fractional_scale_v1. Taken from the
+    // fractional scale if the compositor offers it, then wl_surface's
+    // preferred buffer scale, then the outputs the window is on.
+    scale_120: u32,
+    fractional_scale_manager: Option<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1>,
+    fractional_scale: Option<wp_fractional_scale_v1::WpFractionalScaleV1>,
+    fractional_scale_120: Option<u32>,
+    preferred_buffer_scale: Option<i32>,
+    outputs: Vec<Output>,
 
     // XKB
     xkb_state: Option<xkb::State>,
     xkb_context: Option<xkb::Context>,
     xkb_keymap: Option<xkb::Keymap>,
-    keystate: KeyState,
+
+    // Input
+    keyboard: KeyboardInput,
+    mouse: MouseInput,
+    gamepads: Gamepads,
+    bindings: Bindings,
+    actions: [ButtonState; Action::COUNT],
     running: bool,
 
+    // Frame clock
+    update_accumulator: f32,
+
     // Application
-    game: Rc<RefCell<handmade_hero::Game>>,
+    memory: Rc<RefCell<PlatformMemory>>,
+    game_code: Rc<RefCell<GameCode>>,
+    volume: Rc<Cell<f32>>,
+    recorder: LoopRecorder,
+    requests: PlatformRequests,
+    applied_requests: PlatformRequests,
+}
+
+struct Output {
+    global_name: u32,
+    output: wl_output::WlOutput,
+    scale: i32,
+    // Whether part of our surface is on this output
+    entered: bool,
 }
 
 impl WaylandState {
@@ -412,49 +1063,116 @@ impl WaylandState {
             compositor: None,
             xdg_wm_base: None,
             surface: None,
+            pointer: None,
+            pointer_serial: 0,
+            relative_pointer_manager: None,
+            relative_pointer: None,
+            pointer_constraints: None,
+            locked_pointer: None,
+            cursor_shape_manager: None,
+            cursor_shape_device: None,
+            cursor_theme: None,
+            cursor_surface: None,
             xdg_surface: None,
             xdg_toplevel: None,
-            pool: None,
-            data: None,
+            window: WindowState::default(),
+            decoration_manager: None,
+            decoration: None,
+            client_side_decorations: true,
+            pointer_position: (0.0, 0.0),
+            swapchain: None,
+            viewporter: None,
+            viewport: None,
+            backbuffer: vec![0; GAME_WIDTH as usize * GAME_HEIGHT as usize * BYTES_PER_PIXEL],
+            scale_120: 120,
+            fractional_scale_manager: None,
+            fractional_scale: None,
+            fractional_scale_120: None,
+            preferred_buffer_scale: None,
+            outputs: Vec::new(),
             height,
             width,
-            game: Rc::new(RefCell::new(handmade_hero::Game::new())),
+            pending_size: None,
+            memory: Rc::new(RefCell::new(
+                allocate_platform_memory(
+                    PERMANENT_STORAGE_SIZE,
+                    TRANSIENT_STORAGE_SIZE,
+                    SOUND_SAMPLES_SIZE,
+                )
+                .expect("should be able to allocate game memory"),
+            )),
+            game_code: Rc::new(RefCell::new(GameCode::new())),
+            volume: Rc::new(Cell::new(1.0)),
+            recorder: LoopRecorder::new(),
+            requests: PlatformRequests::default(),
+            applied_requests: PlatformRequests::default(),
             xkb_state: None,
             xkb_context: None,
             xkb_keymap: None,
-            bytes_per_pixel: BYTES_PER_PIXEL,
-            keystate: KeyState::new(),
+            keyboard: KeyboardInput::new(),
+            mouse: MouseInput::default(),
+            gamepads: Gamepads::new(),
+            bindings: Bindings::load(),
+            actions: [ButtonState::default(); Action::COUNT],
             running: true,
+            update_accumulator: 0.0,
         }
     }
 }
 
+// Initial window size
 const RESOLUTION_WIDTH: i32 = 1920;
 const RESOLUTION_HEIGHT: i32 = 1080;
-const BYTES_PER_PIXEL: i32 = 4;
+// What the game renders at, whatever the window size
+const GAME_WIDTH: i32 = 960;
+const GAME_HEIGHT: i32 = 540;
 const SAMPLE_RATE: u32 = 48000;
+// Used for the cursor when the compositor can't draw it for us
+const DEFAULT_CURSOR: &str = "default";
+const DEFAULT_CURSOR_SIZE: u32 = 24;
 const NUM_CHANNELS: u8 = 2;
+// Game audio volume while another window has focus
+const BACKGROUND_VOLUME: f32 = 0.25;
+const MAX_EPOLL_EVENTS: usize = 8;
+// Gameplay advances in fixed steps, however often the compositor asks for frames
+const SECONDS_PER_UPDATE: f32 = 1.0 / 120.0;
+// Drop time we could not catch up on, e.g. after the window was hidden
+const MAX_SECONDS_PER_FRAME: f32 = 0.25;
+const PERMANENT_STORAGE_SIZE: usize = 64 * 1024 * 1024;
+const TRANSIENT_STORAGE_SIZE: usize = 128 * 1024 * 1024;
+// Two seconds of audio, more than PulseAudio asks for in a single write
+const SOUND_SAMPLES_SIZE: usize =
+    2 * SAMPLE_RATE as usize * NUM_CHANNELS as usize * std::mem::size_of::<f32>();
 
 fn main() {
     // Setup wayland event queue
     let (mut state, mut event_queue) = wl_init(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
-    let wayland_fd = event_queue.as_fd();
+    let wayland_fd = event_queue.as_fd().as_raw_fd();
     let wayland_event = epoll::Event {
         events: libc::EPOLLIN as u32,
-        data: wayland_fd.as_raw_fd() as u64,
+        data: wayland_fd as u64,
     };
     let epoll_fd = epoll::create(true).unwrap();
     epoll::ctl(
         epoll_fd,
         epoll::ControlOptions::EPOLL_CTL_ADD,
-        wayland_fd.as_raw_fd(),
+        wayland_fd,
         wayland_event,
     )
     .unwrap();
 
-    let pulse_mainloop = pulse_init(&state.game, SAMPLE_RATE, NUM_CHANNELS);
+    let pulse_mainloop = pulse_init(
+        &state.memory,
+        &state.game_code,
+        &state.volume,
+        SAMPLE_RATE,
+        NUM_CHANNELS,
+    );
+    let mut epoll_bridge = pulse_attach_epoll(&mut pulse_mainloop.borrow_mut(), epoll_fd);
+    state.gamepads.register(epoll_fd);
 
     // Main loop
+    let mut events = vec![epoll::Event { data: 0, events: 0 }; MAX_EPOLL_EVENTS];
     while state.running {
         // Flush outgoing wayland events
         event_queue.flush().unwrap();
@@ -463,15 +1181,31 @@ fn main() {
         // Synchronise read from event queue.
         let read_guard = event_queue.prepare_read().unwrap();
 
-        // Check sockets to see if ready
-        let mut events = vec![epoll::Event { data: 0, events: 0 }];
-        let wayland_socket_ready = epoll::wait(epoll_fd, 0, &mut events).unwrap() != 0;
+        // Sleep until audio or one of our sockets needs attention
+        let epoll_ready = pulse_wait(&mut pulse_mainloop.borrow_mut(), &mut epoll_bridge);
+
+        let mut wayland_socket_ready = false;
+        if epoll_ready {
+            let num_events = epoll::wait(epoll_fd, 0, &mut events).unwrap();
+            for event in &events[..num_events] {
+                let fd = event.data as RawFd;
+                if fd == wayland_fd {
+                    wayland_socket_ready = true;
+                } else if state.gamepads.owns(fd) {
+                    let command =
+                        state
+                            .gamepads
+                            .process(fd, &mut state.bindings, &mut state.actions);
+                    if let Some(command) = command {
+                        platform_command_run(&mut state, command);
+                    }
+                }
+            }
+        }
 
         if wayland_socket_ready {
             read_guard.read().unwrap();
             event_queue.dispatch_pending(&mut state).unwrap();
-        } else {
-            pulse_mainloop.borrow_mut().iterate(false);
         }
     }
 }
@@ -502,6 +1236,15 @@ fn wl_init(width: i32, height: i32) -> (WaylandState, EventQueue<WaylandState>)
     // Ask the compositor for a surface
     state.surface = Some(state.compositor.as_ref().unwrap().create_surface(&qh, ()));
 
+    // Without cursor-shape-v1 we draw the default cursor ourselves
+    if state.cursor_shape_manager.is_none() {
+        state.cursor_theme = cursor_theme_load(&conn, state.shm.as_ref().unwrap());
+        if state.cursor_theme.is_some() {
+            let compositor = state.compositor.as_ref().unwrap();
+            state.cursor_surface = Some(compositor.create_surface(&qh, CursorSurface));
+        }
+    }
+
     // Convert the surface into an xdg_surface for desktop applications
     state.xdg_surface = Some(state.xdg_wm_base.as_ref().unwrap().get_xdg_surface(
         state.surface.as_ref().unwrap(),
@@ -519,20 +1262,31 @@ fn wl_init(width: i32, height: i32) -> (WaylandState, EventQueue<WaylandState>)
         .unwrap()
         .set_title("Handmade Hero".to_string());
 
-    // draw frame
-    let stride: i32 = width * BYTES_PER_PIXEL;
-    let size = height * stride;
-    let fd = shm::allocate_shm_file(size).expect("should be able to allocate shared memory");
-    let file = File::from(fd);
+    // Ask for a frame drawn by the compositor. Until it answers, and if it
+    // can't, we draw our own.
+    if let Some(manager) = &state.decoration_manager {
+        let decoration =
+            manager.get_toplevel_decoration(state.xdg_toplevel.as_ref().unwrap(), &qh, ());
+        decoration.set_mode(zxdg_toplevel_decoration_v1::Mode::ServerSide);
+        state.decoration = Some(decoration);
+    }
 
-    state.data = Some(unsafe { memmap::MmapOptions::new().map_mut(&file).unwrap() });
+    // Let the compositor stretch our buffers over the window if it can.
+    // Fractional scales can only be drawn at through a viewport.
+    if let Some(viewporter) = &state.viewporter {
+        let surface = state.surface.as_ref().unwrap();
+        state.viewport = Some(viewporter.get_viewport(surface, &qh, ()));
+        if let Some(manager) = &state.fractional_scale_manager {
+            state.fractional_scale = Some(manager.get_fractional_scale(surface, &qh, ()));
+        }
+    }
 
-    state.pool = Some(state.shm.as_ref().unwrap().create_pool(
-        file.as_fd(),
-        size.try_into().unwrap(),
-        &qh,
-        (),
-    ));
+    // Buffers handed to the compositor, sized by the first layout
+    state.swapchain = Some(
+        Swapchain::new(state.shm.as_ref().unwrap(), width, height, &qh)
+            .expect("should be able to allocate the backbuffer"),
+    );
+    wl_surface_layout(&mut state);
 
     // draw_frame(&mut state, &qh);
     state.surface.as_ref().unwrap().commit();
@@ -541,45 +1295,272 @@ fn wl_init(width: i32, height: i32) -> (WaylandState, EventQueue<WaylandState>)
     return (state, event_queue);
 }
 
-fn wl_frame_draw(state: &mut WaylandState, qh: &QueueHandle<WaylandState>) {
-    let height = state.height;
+fn wl_frame_update(state: &mut WaylandState, qh: &QueueHandle<WaylandState>, elapsed_ms: u32) {
+    // Pick up a rebuilt game library before running the next frame
+    state.game_code.borrow_mut().reload_if_changed();
+
+    // Run as many fixed updates as fit in the time since the last frame and
+    // carry the remainder over to the next one
+    let elapsed = (elapsed_ms as f32 / 1000.0).min(MAX_SECONDS_PER_FRAME);
+    state.update_accumulator += elapsed;
+
+    {
+        let memory = &mut *state.memory.borrow_mut();
+        let update = state.game_code.borrow().update;
+        while state.update_accumulator >= SECONDS_PER_UPDATE {
+            let mut input = GameInput {
+                dt: SECONDS_PER_UPDATE,
+                keyboard: state.keyboard,
+                mouse: state.mouse,
+                controllers: state.gamepads.controllers,
+                actions: state.actions,
+                window: state.window,
+            };
+
+            // Record or replace this update's input if an input loop is active
+            state.recorder.process_input(memory, &mut input);
+            update(&mut memory.game, &input, &mut state.requests);
+            state.update_accumulator -= SECONDS_PER_UPDATE;
+
+            // Transitions and text are only reported to the first update
+            state.keyboard.begin_update();
+            state.mouse.begin_update();
+            state.gamepads.begin_update();
+            for action in &mut state.actions {
+                action.begin_update();
+            }
+        }
+    }
+
+    platform_requests_apply(state, qh);
+}
+
+// Acts on whatever the game asked for that differs from what it asked for last
+fn platform_requests_apply(state: &mut WaylandState, qh: &QueueHandle<WaylandState>) {
+    let requests = state.requests;
+
+    if requests.mouse_look != state.applied_requests.mouse_look {
+        pointer_lock_set(state, qh, requests.mouse_look);
+    }
+
+    // The compositor answers with a configure, which is when the window
+    // state the game sees changes
+    let toplevel = state.xdg_toplevel.as_ref().unwrap();
+    if requests.fullscreen != state.applied_requests.fullscreen {
+        if requests.fullscreen {
+            toplevel.set_fullscreen(None);
+        } else {
+            toplevel.unset_fullscreen();
+        }
+    }
+    if requests.maximized != state.applied_requests.maximized {
+        if requests.maximized {
+            toplevel.set_maximized();
+        } else {
+            toplevel.unset_maximized();
+        }
+    }
+
+    state.applied_requests = requests;
+}
+
+// Hides the cursor over the window while the pointer is locked and shows the
+// default one otherwise. Without cursor-shape-v1 the default one comes from
+// the cursor theme. With neither the cursor is never hidden, since there would
+// be no way to bring it back.
+fn pointer_cursor_update(state: &mut WaylandState, serial: u32) {
+    let Some(pointer) = &state.pointer else {
+        return;
+    };
+
+    if state.cursor_shape_device.is_none() && state.cursor_theme.is_none() {
+        return;
+    }
+    if state.mouse.locked {
+        pointer.set_cursor(serial, None, 0, 0);
+        return;
+    }
+
+    if let Some(device) = &state.cursor_shape_device {
+        device.set_shape(serial, wp_cursor_shape_device_v1::Shape::Default);
+    } else if let (Some(theme), Some(surface)) = (&mut state.cursor_theme, &state.cursor_surface) {
+        // Checked for when the theme was loaded
+        let image = &theme.get_cursor(DEFAULT_CURSOR).unwrap()[0];
+        let (width, height) = image.dimensions();
+        let (hotspot_x, hotspot_y) = image.hotspot();
+        surface.attach(Some(image), 0, 0);
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        surface.commit();
+        pointer.set_cursor(serial, Some(surface), hotspot_x as i32, hotspot_y as i32);
+    }
+}
+
+// The cursor theme picked by $XCURSOR_THEME and $XCURSOR_SIZE, if it has a
+// default cursor
+fn cursor_theme_load(conn: &Connection, shm: &wl_shm::WlShm) -> Option<CursorTheme> {
+    let size = env::var("XCURSOR_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_CURSOR_SIZE);
+    let mut theme = CursorTheme::load(conn, shm.clone(), size).ok()?;
+    if theme.get_cursor(DEFAULT_CURSOR).is_none() {
+        eprintln!("Cursor theme has no default cursor, the cursor stays visible in mouse-look");
+        return None;
+    }
+    Some(theme)
+}
+
+fn pointer_lock_set(state: &mut WaylandState, qh: &QueueHandle<WaylandState>, lock: bool) {
+    if !lock {
+        if let Some(locked_pointer) = state.locked_pointer.take() {
+            locked_pointer.destroy();
+        }
+        state.mouse.locked = false;
+        pointer_cursor_update(state, state.pointer_serial);
+        return;
+    }
+
+    match (&state.pointer_constraints, &state.pointer, &state.surface) {
+        (Some(constraints), Some(pointer), Some(surface)) => {
+            state.locked_pointer = Some(constraints.lock_pointer(
+                surface,
+                pointer,
+                None,
+                zwp_pointer_constraints_v1::Lifetime::Persistent,
+                qh,
+                (),
+            ));
+        }
+        _ => eprintln!("Compositor does not support pointer locking"),
+    }
+}
+
+fn wl_scale_update(state: &mut WaylandState) {
+    let scale_120 = if let Some(scale_120) = state.fractional_scale_120 {
+        scale_120
+    } else if let Some(scale) = state.preferred_buffer_scale {
+        scale.max(1) as u32 * 120
+    } else {
+        let scale = state
+            .outputs
+            .iter()
+            .filter(|o| o.entered)
+            .map(|o| o.scale)
+            .max()
+            .unwrap_or(1);
+        scale.max(1) as u32 * 120
+    };
+
+    if scale_120 != state.scale_120 {
+        state.scale_120 = scale_120;
+        wl_surface_layout(state);
+    }
+}
+
+// Sizes the swapchain for the current window size and scale
+fn wl_surface_layout(state: &mut WaylandState) {
+    // Scale events can arrive before the window is set up
+    if state.swapchain.is_none() {
+        return;
+    }
+
     let width = state.width;
-    let bytes_per_pixel = state.bytes_per_pixel;
-    let data = state.data.as_mut().unwrap();
-    let stride = width * bytes_per_pixel;
+    let height = state.height;
+    let scale_120 = state.scale_120 as i32;
+
+    let (buffer_width, buffer_height, buffer_scale) = match &state.viewport {
+        Some(viewport) => {
+            viewport.set_destination(width, height);
+            // The title bar has to be drawn at the window's resolution
+            if scale_120 == 120 && !state.client_side_decorations {
+                (GAME_WIDTH, GAME_HEIGHT, 1)
+            } else {
+                // One buffer pixel per physical pixel, rounded like the
+                // fractional scale protocol asks for
+                let physical = |size: i32| (size * scale_120 + 60) / 120;
+                (physical(width), physical(height), 1)
+            }
+        }
+        None => {
+            // Without a viewport only whole scales can be drawn at
+            let scale = (scale_120 + 119) / 120;
+            (width * scale, height * scale, scale)
+        }
+    };
+
+    // The scale is set with the next buffer, see `wl_frame_draw`
+    if let Err(e) =
+        state
+            .swapchain
+            .as_mut()
+            .unwrap()
+            .resize(buffer_width, buffer_height, buffer_scale)
+    {
+        eprintln!("{}", e);
+    }
+}
+
+fn wl_frame_draw(state: &mut WaylandState, qh: &QueueHandle<WaylandState>) {
+    let title_bar_height = title_bar_height(state);
+    let swapchain = state.swapchain.as_mut().unwrap();
+    let Some(index) = swapchain.acquire(qh) else {
+        // The compositor still holds every buffer, keep showing the last frame
+        return;
+    };
 
-    // Draw checkboxed background
-    let mut pixel_buffer = PixelBuffer {
-        data,
+    let height = swapchain.height;
+    let width = swapchain.width;
+    let stride = swapchain.stride;
+    let target = PixelBuffer {
+        data: swapchain.pixels(index),
         height,
         width,
         stride,
     };
 
-    state
-        .game
-        .borrow_mut()
-        .update_and_render(&mut pixel_buffer, &state.keystate);
-
-    let buffer = Some(state.pool.as_ref().unwrap().create_buffer(
-        0,
+    // Split off the rows the title bar covers
+    let scale = width as f32 / state.width as f32;
+    let title_bar = ((title_bar_height as f32 * scale) as i32).min(height);
+    let (bar, content) = target.data.split_at_mut((title_bar * stride) as usize);
+    let mut content = PixelBuffer {
+        data: content,
+        height: height - title_bar,
         width,
-        height,
         stride,
-        wl_shm::Format::Xrgb8888,
-        &qh,
-        (),
-    ));
+    };
 
-    state
-        .surface
-        .as_ref()
-        .unwrap()
-        .attach(buffer.as_ref(), 0, 0);
+    if title_bar > 0 {
+        let mut bar = PixelBuffer {
+            data: bar,
+            height: title_bar,
+            width,
+            stride,
+        };
+        let (x, y) = state.pointer_position;
+        let hover = state
+            .mouse
+            .in_window
+            .then(|| hit_test(x, y, state.width, state.height, false));
+        draw_title_bar(&mut bar, scale, state.window.activated, hover);
+    }
 
-    state
-        .surface
-        .as_ref()
-        .unwrap()
-        .damage_buffer(0, 0, state.width, state.height);
+    let render = state.game_code.borrow().render;
+    let game = &mut state.memory.borrow_mut().game;
+    if content.width == GAME_WIDTH && content.height == GAME_HEIGHT {
+        render(game, &mut content);
+    } else {
+        let mut backbuffer = PixelBuffer {
+            data: &mut state.backbuffer,
+            height: GAME_HEIGHT,
+            width: GAME_WIDTH,
+            stride: GAME_WIDTH * BYTES_PER_PIXEL as i32,
+        };
+        render(game, &mut backbuffer);
+        scale_nearest(&backbuffer, &mut content);
+    }
+
+    let surface = state.surface.as_ref().unwrap();
+    swapchain.attach(index, surface);
+    surface.set_buffer_scale(swapchain.scale);
+    surface.damage_buffer(0, 0, width, height);
 }
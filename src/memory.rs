@@ -0,0 +1,80 @@
+use std::{env, fs, slice};
+
+use handmade_hero::GameMemory;
+use libc::{self};
+
+// Mapping the block at the same address every run keeps any pointers the game
+// stores inside it valid across code reloads and state snapshots.
+const BASE_ADDRESS: usize = 0x2000_0000_0000; // 32 TiB
+
+pub struct PlatformMemory {
+    pub game: GameMemory,
+    pub sound_samples: &'static mut [u8],
+    pub loop_snapshot: &'static mut [u8],
+}
+
+// Memory is laid out as [permanent | transient | sound samples | loop snapshot]
+// in a single anonymous mapping that is never freed. The snapshot is only
+// touched while recording, so it costs nothing until then.
+pub fn allocate_platform_memory(
+    permanent_storage_size: usize,
+    transient_storage_size: usize,
+    sound_samples_size: usize,
+) -> Result<PlatformMemory, &'static str> {
+    let game_memory_size = permanent_storage_size + transient_storage_size;
+    let size = game_memory_size + sound_samples_size + game_memory_size;
+
+    let base = unsafe {
+        libc::mmap(
+            BASE_ADDRESS as *mut libc::c_void,
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+            0,
+        )
+    };
+
+    if base == libc::MAP_FAILED {
+        return Err("Unable to allocate game memory");
+    }
+
+    let base = base as *mut u8;
+    if base as usize != BASE_ADDRESS {
+        eprintln!("Game memory mapped at {:p} instead of the fixed base", base);
+    }
+
+    unsafe {
+        let transient_storage = base.add(permanent_storage_size);
+        let sound_samples = transient_storage.add(transient_storage_size);
+        let loop_snapshot = sound_samples.add(sound_samples_size);
+
+        Ok(PlatformMemory {
+            game: GameMemory {
+                is_initialized: false,
+                permanent_storage_size,
+                permanent_storage: base,
+                transient_storage_size,
+                transient_storage,
+                read_asset,
+            },
+            sound_samples: slice::from_raw_parts_mut(sound_samples, sound_samples_size),
+            loop_snapshot: slice::from_raw_parts_mut(loop_snapshot, game_memory_size),
+        })
+    }
+}
+
+// Handed to the game to read its assets. They live in `assets` next to the
+// executable or in the closest directory above it that has one, which for
+// cargo builds is the repository.
+fn read_asset(name: &str) -> Result<Vec<u8>, &'static str> {
+    let exe = env::current_exe().map_err(|_| "Unable to locate the executable")?;
+    let dir = exe
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("assets"))
+        .find(|dir| dir.is_dir())
+        .ok_or("No assets directory next to the executable")?;
+
+    fs::read(dir.join(name)).map_err(|_| "Unable to read asset")
+}
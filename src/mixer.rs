@@ -0,0 +1,243 @@
+use crate::{Oscillator, SamplePlayer, SoundBuffer};
+
+// Voices beyond this are not started
+pub const MAX_VOICES: usize = 32;
+
+// Louder samples are bent towards 1 instead of being cut off
+const CLIP_KNEE: f32 = 0.9;
+
+// What a voice plays
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum Source {
+    Silence,
+    Oscillator(Oscillator),
+    Sample(SamplePlayer),
+}
+
+impl Source {
+    // Left and right of the next frame
+    fn next(&mut self, sample_rate: u32, pitch: f32) -> [f32; 2] {
+        match self {
+            Source::Silence => [0.0; 2],
+            Source::Oscillator(oscillator) => [oscillator.next_pitched(sample_rate, pitch); 2],
+            Source::Sample(player) => player.next(sample_rate, pitch),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        match self {
+            Source::Sample(player) => player.is_finished(),
+            _ => false,
+        }
+    }
+}
+
+// Refers to a voice for as long as it plays. Once the voice has finished and
+// its slot is reused the id goes stale and is ignored.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VoiceId {
+    index: u32,
+    generation: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Voice {
+    source: Source,
+    generation: u32,
+    playing: bool,
+    volume: f32,
+    // -1 is hard left, 1 hard right. Centered plays both channels at full
+    // volume.
+    pan: f32,
+    // Playback speed, 2 is an octave up
+    pitch: f32,
+    // Fade envelope, moving linearly towards `fade_target` by `fade_rate`
+    // per second
+    gain: f32,
+    fade_target: f32,
+    fade_rate: f32,
+    stop_when_faded: bool,
+}
+
+impl Voice {
+    const IDLE: Voice = Voice {
+        source: Source::Silence,
+        generation: 0,
+        playing: false,
+        volume: 0.0,
+        pan: 0.0,
+        pitch: 1.0,
+        gain: 0.0,
+        fade_target: 0.0,
+        fade_rate: 0.0,
+        stop_when_faded: false,
+    };
+
+    fn fade(&mut self, target: f32, seconds: f32) {
+        self.fade_target = target;
+        // From wherever the gain is now
+        self.fade_rate = if seconds > 0.0 {
+            (target - self.gain).abs() / seconds
+        } else {
+            0.0
+        };
+    }
+
+    fn advance_fade(&mut self, sample_rate: u32) {
+        if self.gain == self.fade_target {
+            return;
+        }
+
+        let step = self.fade_rate / sample_rate as f32;
+        if self.fade_rate == 0.0 || (self.fade_target - self.gain).abs() <= step {
+            self.gain = self.fade_target;
+        } else if self.fade_target > self.gain {
+            self.gain += step;
+        } else {
+            self.gain -= step;
+        }
+    }
+}
+
+// Sums every playing voice into the sound buffer. Lives in game memory, so it
+// is plain data with a fixed number of voices.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Mixer {
+    voices: [Voice; MAX_VOICES],
+    pub master_volume: f32,
+}
+
+impl Mixer {
+    pub fn new() -> Mixer {
+        Mixer {
+            voices: [Voice::IDLE; MAX_VOICES],
+            master_volume: 1.0,
+        }
+    }
+
+    // Starts `source`, fading in over `fade_in` seconds. Returns None if every
+    // voice is busy.
+    pub fn play(&mut self, source: Source, volume: f32, pan: f32, fade_in: f32) -> Option<VoiceId> {
+        let index = self.voices.iter().position(|voice| !voice.playing)?;
+        let voice = &mut self.voices[index];
+
+        *voice = Voice {
+            source,
+            generation: voice.generation.wrapping_add(1),
+            playing: true,
+            volume,
+            pan: pan.clamp(-1.0, 1.0),
+            gain: if fade_in > 0.0 { 0.0 } else { 1.0 },
+            ..Voice::IDLE
+        };
+        voice.fade(1.0, fade_in);
+
+        Some(VoiceId {
+            index: index as u32,
+            generation: voice.generation,
+        })
+    }
+
+    // Fades the voice out over `fade_out` seconds and frees it afterwards.
+    // Sounds that aren't looping also stop by themselves at their end.
+    pub fn stop(&mut self, id: VoiceId, fade_out: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.fade(0.0, fade_out);
+            voice.stop_when_faded = true;
+        }
+    }
+
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        let voice = &self.voices[id.index as usize];
+        voice.playing && voice.generation == id.generation
+    }
+
+    pub fn set_volume(&mut self, id: VoiceId, volume: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.volume = volume;
+        }
+    }
+
+    pub fn set_pan(&mut self, id: VoiceId, pan: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn set_pitch(&mut self, id: VoiceId, pitch: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.pitch = pitch;
+        }
+    }
+
+    // For changing what a playing voice plays, e.g. an oscillator's frequency
+    pub fn source_mut(&mut self, id: VoiceId) -> Option<&mut Source> {
+        self.voice_mut(id).map(|voice| &mut voice.source)
+    }
+
+    // Overwrites the sound buffer, which holds f32 samples. The first two
+    // channels are left and right, any others get the voices unpanned.
+    pub fn mix(&mut self, sound_buffer: &mut SoundBuffer) {
+        let sample_rate = sound_buffer.sample_rate;
+        let bytes_per_sample = sound_buffer.bytes_per_sample;
+        let frame_size = bytes_per_sample * sound_buffer.num_channels as usize;
+
+        for frame in sound_buffer.data.chunks_exact_mut(frame_size) {
+            let mut left = 0.0;
+            let mut right = 0.0;
+            let mut center = 0.0;
+
+            for voice in self.voices.iter_mut().filter(|voice| voice.playing) {
+                let [l, r] = voice.source.next(sample_rate, voice.pitch);
+                let gain = voice.volume * voice.gain;
+                left += l * gain * (1.0 - voice.pan).min(1.0);
+                right += r * gain * (1.0 + voice.pan).min(1.0);
+                center += (l + r) * 0.5 * gain;
+
+                voice.advance_fade(sample_rate);
+                if voice.source.is_finished() || (voice.stop_when_faded && voice.gain == 0.0) {
+                    voice.playing = false;
+                }
+            }
+
+            let mono = sound_buffer.num_channels == 1;
+            for (channel, sample) in frame.chunks_exact_mut(bytes_per_sample).enumerate() {
+                let value = match channel {
+                    _ if mono => center,
+                    0 => left,
+                    1 => right,
+                    _ => center,
+                };
+                sample.copy_from_slice(&soft_clip(value * self.master_volume).to_ne_bytes());
+            }
+        }
+    }
+
+    fn voice_mut(&mut self, id: VoiceId) -> Option<&mut Voice> {
+        let voice = &mut self.voices[id.index as usize];
+        (voice.playing && voice.generation == id.generation).then_some(voice)
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Leaves samples up to the knee alone and bends louder ones smoothly towards
+// 1, so that many loud voices at once saturate instead of wrapping or cracking
+fn soft_clip(value: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= CLIP_KNEE {
+        return value;
+    }
+
+    let headroom = 1.0 - CLIP_KNEE;
+    let bent = CLIP_KNEE + headroom * ((magnitude - CLIP_KNEE) / headroom).tanh();
+    bent.copysign(value)
+}
@@ -0,0 +1,135 @@
+use std::f64::consts::TAU;
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Saw,
+    Triangle,
+    Noise,
+}
+
+impl Waveform {
+    pub const COUNT: usize = 5;
+    pub const ALL: [Waveform; Waveform::COUNT] = [
+        Waveform::Sine,
+        Waveform::Square,
+        Waveform::Saw,
+        Waveform::Triangle,
+        Waveform::Noise,
+    ];
+
+    pub fn next(self) -> Waveform {
+        Waveform::ALL[(self as usize + 1) % Waveform::COUNT]
+    }
+}
+
+// A phase-accumulator oscillator. The phase only ever moves forward by the
+// current frequency, so changing the pitch bends the tone instead of jumping
+// to a different point of the wave. Frequency and amplitude glide towards the
+// values they are set to rather than changing abruptly.
+//
+// Lives in game memory, so it is plain data.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Oscillator {
+    pub waveform: Waveform,
+    // Seconds it takes to get about two thirds of the way to a new frequency
+    // or amplitude
+    pub glide_time: f32,
+    // Position within the current cycle, in [0, 1). Kept small so it stays
+    // just as precise after hours of playing as it was at the start.
+    phase: f64,
+    frequency: f32,
+    target_frequency: f32,
+    amplitude: f32,
+    target_amplitude: f32,
+    noise_state: u32,
+}
+
+impl Oscillator {
+    pub fn new(waveform: Waveform, frequency: f32, amplitude: f32) -> Oscillator {
+        Oscillator {
+            waveform,
+            glide_time: 0.02,
+            phase: 0.0,
+            frequency,
+            target_frequency: frequency,
+            amplitude,
+            target_amplitude: amplitude,
+            noise_state: 0x2545_f491,
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.target_frequency = frequency;
+    }
+
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        self.target_amplitude = amplitude;
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    // Advances by one sample and returns it
+    pub fn next(&mut self, sample_rate: u32) -> f32 {
+        self.next_pitched(sample_rate, 1.0)
+    }
+
+    // Like `next`, playing `pitch` times faster than the set frequency
+    pub fn next_pitched(&mut self, sample_rate: u32, pitch: f32) -> f32 {
+        let sample_rate = sample_rate as f32;
+
+        // One-pole smoothing towards the targets
+        let glide = if self.glide_time > 0.0 {
+            1.0 - (-1.0 / (self.glide_time * sample_rate)).exp()
+        } else {
+            1.0
+        };
+        self.frequency = approach(self.frequency, self.target_frequency, glide);
+        self.amplitude = approach(self.amplitude, self.target_amplitude, glide);
+
+        let phase = self.phase;
+        let value = match self.waveform {
+            Waveform::Sine => (phase * TAU).sin() as f32,
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Saw => (2.0 * phase - 1.0) as f32,
+            Waveform::Triangle => (1.0 - 4.0 * (phase - 0.5).abs()) as f32,
+            Waveform::Noise => self.noise(),
+        };
+
+        let step = self.frequency as f64 * pitch as f64 / sample_rate as f64;
+        self.phase = (self.phase + step).rem_euclid(1.0);
+        value * self.amplitude
+    }
+
+    // White noise in [-1, 1] from a xorshift generator
+    fn noise(&mut self) -> f32 {
+        let mut x = self.noise_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_state = x;
+
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+fn approach(value: f32, target: f32, glide: f32) -> f32 {
+    let next = value + (target - value) * glide;
+    // Once the steps are too small to change the value, land on the target
+    if next == value {
+        target
+    } else {
+        next
+    }
+}
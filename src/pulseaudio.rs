@@ -1,6 +1,17 @@
-use std::{cell::RefCell, mem::size_of, ops::Deref, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::min,
+    ffi::{c_ulong, c_void},
+    mem::size_of,
+    ops::Deref,
+    os::fd::RawFd,
+    rc::Rc,
+    slice,
+};
 
-use handmade_hero::{Game, SoundBuffer};
+use crate::{game_code::GameCode, memory::PlatformMemory};
+use handmade_hero::SoundBuffer;
+use libc::{self, pollfd};
 use pulse::{
     context::{self, Context, FlagSet},
     mainloop::standard::Mainloop,
@@ -10,7 +21,9 @@ use pulse::{
 };
 
 pub fn pulse_init(
-    game: &Rc<RefCell<Game>>,
+    memory: &Rc<RefCell<PlatformMemory>>,
+    game_code: &Rc<RefCell<GameCode>>,
+    volume: &Rc<Cell<f32>>,
     sample_rate: u32,
     num_channels: u8,
 ) -> Rc<RefCell<Mainloop>> {
@@ -73,23 +86,37 @@ pub fn pulse_init(
 
     // Setup write callback
     let stream_ref = Rc::clone(&stream);
-    let game_ref = Rc::clone(game);
+    let memory_ref = Rc::clone(memory);
+    let game_code_ref = Rc::clone(game_code);
+    let volume_ref = Rc::clone(volume);
     stream
         .borrow_mut()
         .set_write_callback(Some(Box::new(move |length: usize| {
-            let data: Vec<u8> = vec![0; length];
-            let mut sound_buffer = SoundBuffer {
-                data,
-                bytes_per_sample: size_of::<f32>(),
-                sample_rate,
-                num_channels,
-            };
-
-            game_ref.borrow_mut().play_sound(&mut sound_buffer);
-            stream_ref
-                .borrow_mut()
-                .write(sound_buffer.data.as_slice(), None, 0, SeekMode::Relative)
-                .unwrap();
+            let memory = &mut *memory_ref.borrow_mut();
+            let play_sound = game_code_ref.borrow().play_sound;
+
+            // Fill the request in chunks in case it is larger than the sample
+            // buffer the platform set aside.
+            let frame_size = size_of::<f32>() * num_channels as usize;
+            let max_chunk = memory.sound_samples.len() / frame_size * frame_size;
+            let mut remaining = length;
+            while remaining > 0 {
+                let chunk = min(remaining, max_chunk);
+                let mut sound_buffer = SoundBuffer {
+                    data: &mut memory.sound_samples[..chunk],
+                    bytes_per_sample: size_of::<f32>(),
+                    sample_rate,
+                    num_channels,
+                };
+
+                play_sound(&mut memory.game, &mut sound_buffer);
+                apply_volume(sound_buffer.data, volume_ref.get());
+                stream_ref
+                    .borrow_mut()
+                    .write(sound_buffer.data, None, 0, SeekMode::Relative)
+                    .unwrap();
+                remaining -= chunk;
+            }
         })));
 
     stream
@@ -105,3 +132,82 @@ pub fn pulse_init(
 
     return mainloop;
 }
+
+// Scales the game's samples, e.g. to duck or mute it while in the background
+fn apply_volume(data: &mut [u8], volume: f32) {
+    if volume == 1.0 {
+        return;
+    }
+
+    for sample in data.chunks_exact_mut(size_of::<f32>()) {
+        let value = f32::from_ne_bytes(sample.try_into().unwrap()) * volume;
+        sample.copy_from_slice(&value.to_ne_bytes());
+    }
+}
+
+// Lets the PulseAudio mainloop block on our epoll set alongside its own file
+// descriptors, so a single poll wakes up for either audio or everything else.
+pub struct EpollBridge {
+    epoll_fd: RawFd,
+    fds: Vec<pollfd>,
+    epoll_ready: bool,
+}
+
+pub fn pulse_attach_epoll(mainloop: &mut Mainloop, epoll_fd: RawFd) -> Box<EpollBridge> {
+    let mut bridge = Box::new(EpollBridge {
+        epoll_fd,
+        fds: Vec::new(),
+        epoll_ready: false,
+    });
+
+    // The bridge is boxed so its address stays put for as long as the caller
+    // keeps it alive.
+    let userdata = bridge.as_mut() as *mut EpollBridge as *mut c_void;
+    mainloop.set_poll_func((epoll_bridge_poll, userdata));
+
+    bridge
+}
+
+// Runs one iteration of the PulseAudio mainloop, sleeping until either audio
+// or the epoll set has work (or a PulseAudio timer fires). Returns whether the
+// epoll set is ready.
+pub fn pulse_wait(mainloop: &mut Mainloop, bridge: &mut EpollBridge) -> bool {
+    bridge.epoll_ready = false;
+
+    mainloop.prepare(None).unwrap();
+    mainloop.poll().unwrap();
+    mainloop.dispatch().unwrap();
+
+    bridge.epoll_ready
+}
+
+extern "C" fn epoll_bridge_poll(
+    ufds: *mut pollfd,
+    nfds: c_ulong,
+    timeout: i32,
+    userdata: *mut c_void,
+) -> i32 {
+    let bridge = unsafe { &mut *(userdata as *mut EpollBridge) };
+    let pulse_fds = unsafe { slice::from_raw_parts_mut(ufds, nfds as usize) };
+
+    bridge.fds.clear();
+    bridge.fds.extend_from_slice(pulse_fds);
+    bridge.fds.push(pollfd {
+        fd: bridge.epoll_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+
+    let ready = unsafe { libc::poll(bridge.fds.as_mut_ptr(), bridge.fds.len() as _, timeout) };
+    if ready < 0 {
+        return ready;
+    }
+
+    for (pulse_fd, fd) in pulse_fds.iter_mut().zip(&bridge.fds) {
+        pulse_fd.revents = fd.revents;
+    }
+
+    // PulseAudio only wants to hear about its own descriptors
+    bridge.epoll_ready = bridge.fds.last().unwrap().revents & libc::POLLIN != 0;
+    ready - bridge.epoll_ready as i32
+}
@@ -0,0 +1,194 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::PathBuf,
+    process, ptr, slice,
+};
+
+use handmade_hero::GameInput;
+
+use crate::memory::PlatformMemory;
+
+#[derive(Clone, Copy)]
+enum LoopState {
+    Idle,
+    Recording,
+    PlayingBack,
+}
+
+// Records the input fed to the game together with a snapshot of game memory
+// taken when recording started. Playback restores the snapshot and replays
+// the input, starting over whenever it runs out, so game code can be edited
+// and reloaded while the same sequence plays again and again.
+pub struct LoopRecorder {
+    state: LoopState,
+    input_path: PathBuf,
+    input_file: Option<File>,
+    snapshot_initialized: bool,
+}
+
+impl LoopRecorder {
+    pub fn new() -> LoopRecorder {
+        LoopRecorder {
+            state: LoopState::Idle,
+            input_path: env::temp_dir().join(format!("handmade_hero_{}.input", process::id())),
+            input_file: None,
+            snapshot_initialized: false,
+        }
+    }
+
+    // Cycles idle -> recording -> playing back -> idle. Stopping playback
+    // leaves the game wherever the loop happened to be.
+    pub fn toggle(&mut self, memory: &mut PlatformMemory) {
+        let result = match self.state {
+            LoopState::Idle => self.begin_recording(memory),
+            LoopState::Recording => self.begin_playback(memory),
+            LoopState::PlayingBack => {
+                self.input_file = None;
+                self.state = LoopState::Idle;
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            self.input_file = None;
+            self.state = LoopState::Idle;
+        }
+    }
+
+    // Called once per update before the game sees the input. While recording
+    // the input is appended to the recording, while playing back it is
+    // replaced by the next recorded input.
+    pub fn process_input(&mut self, memory: &mut PlatformMemory, input: &mut GameInput) {
+        let result = match self.state {
+            LoopState::Idle => Ok(()),
+            LoopState::Recording => self.record_input(input),
+            LoopState::PlayingBack => self.play_back_input(memory, input),
+        };
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            self.input_file = None;
+            self.state = LoopState::Idle;
+        }
+    }
+
+    fn begin_recording(&mut self, memory: &mut PlatformMemory) -> Result<(), &'static str> {
+        let file = File::create(&self.input_path).map_err(|_| "Unable to create loop recording")?;
+
+        copy_game_memory(memory, Direction::ToSnapshot);
+        self.snapshot_initialized = memory.game.is_initialized;
+        self.input_file = Some(file);
+        self.state = LoopState::Recording;
+        eprintln!("Recording input loop");
+        Ok(())
+    }
+
+    fn begin_playback(&mut self, memory: &mut PlatformMemory) -> Result<(), &'static str> {
+        let file = File::open(&self.input_path).map_err(|_| "Unable to open loop recording")?;
+
+        self.restore_snapshot(memory);
+        self.input_file = Some(file);
+        self.state = LoopState::PlayingBack;
+        eprintln!("Playing back input loop");
+        Ok(())
+    }
+
+    fn record_input(&mut self, input: &GameInput) -> Result<(), &'static str> {
+        // GameInput has no padding, every byte is an initialized field
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                input as *const GameInput as *const u8,
+                size_of::<GameInput>(),
+            )
+        };
+
+        self.input_file
+            .as_mut()
+            .unwrap()
+            .write_all(bytes)
+            .map_err(|_| "Unable to write loop recording")
+    }
+
+    fn play_back_input(
+        &mut self,
+        memory: &mut PlatformMemory,
+        input: &mut GameInput,
+    ) -> Result<(), &'static str> {
+        let mut bytes = [0; size_of::<GameInput>()];
+        let file = self.input_file.as_mut().unwrap();
+
+        match file.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                // End of the loop, jump back to where the recording started
+                file.seek(SeekFrom::Start(0))
+                    .map_err(|_| "Unable to rewind loop recording")?;
+                file.read_exact(&mut bytes)
+                    .map_err(|_| "Loop recording is empty")?;
+                self.restore_snapshot(memory);
+            }
+            Err(_) => return Err("Unable to read loop recording"),
+        }
+
+        // The recording was written by `record_input` from a valid GameInput
+        *input = unsafe { ptr::read_unaligned(bytes.as_ptr() as *const GameInput) };
+        Ok(())
+    }
+
+    fn restore_snapshot(&self, memory: &mut PlatformMemory) {
+        copy_game_memory(memory, Direction::FromSnapshot);
+        memory.game.is_initialized = self.snapshot_initialized;
+    }
+}
+
+enum Direction {
+    ToSnapshot,
+    FromSnapshot,
+}
+
+fn copy_game_memory(memory: &mut PlatformMemory, direction: Direction) {
+    let game = &memory.game;
+    let permanent_size = game.permanent_storage_size;
+    let transient_size = game.transient_storage_size;
+    let (permanent, transient) = memory.loop_snapshot.split_at_mut(permanent_size);
+    let transient = &mut transient[..transient_size];
+
+    unsafe {
+        match direction {
+            Direction::ToSnapshot => {
+                ptr::copy_nonoverlapping(
+                    game.permanent_storage,
+                    permanent.as_mut_ptr(),
+                    permanent_size,
+                );
+                ptr::copy_nonoverlapping(
+                    game.transient_storage,
+                    transient.as_mut_ptr(),
+                    transient_size,
+                );
+            }
+            Direction::FromSnapshot => {
+                ptr::copy_nonoverlapping(
+                    permanent.as_ptr(),
+                    game.permanent_storage,
+                    permanent_size,
+                );
+                ptr::copy_nonoverlapping(
+                    transient.as_ptr(),
+                    game.transient_storage,
+                    transient_size,
+                );
+            }
+        }
+    }
+}
+
+impl Drop for LoopRecorder {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.input_path);
+    }
+}
@@ -0,0 +1,33 @@
+use handmade_hero::{PixelBuffer, BYTES_PER_PIXEL};
+
+// Stretches `src` over the whole of `dst` picking the nearest source pixel,
+// which keeps the game's pixels sharp. Used when the compositor can't scale
+// buffers for us.
+pub fn scale_nearest(src: &PixelBuffer, dst: &mut PixelBuffer) {
+    let src_stride = src.stride as usize;
+    let dst_stride = dst.stride as usize;
+    let row_bytes = dst.width as usize * BYTES_PER_PIXEL;
+
+    let mut previous_src_y = None;
+    for y in 0..dst.height as usize {
+        let src_y = y * src.height as usize / dst.height as usize;
+        let dst_row = y * dst_stride;
+
+        // Scaling up repeats source rows, copy the row we just produced
+        if previous_src_y == Some(src_y) {
+            let previous_row = dst_row - dst_stride;
+            dst.data
+                .copy_within(previous_row..previous_row + row_bytes, dst_row);
+            continue;
+        }
+        previous_src_y = Some(src_y);
+
+        let src_row = &src.data[src_y * src_stride..];
+        let dst_row = &mut dst.data[dst_row..dst_row + row_bytes];
+        for (x, pixel) in dst_row.chunks_exact_mut(BYTES_PER_PIXEL).enumerate() {
+            let src_x = x * src.width as usize / dst.width as usize;
+            let src_pixel = src_x * BYTES_PER_PIXEL;
+            pixel.copy_from_slice(&src_row[src_pixel..src_pixel + BYTES_PER_PIXEL]);
+        }
+    }
+}
@@ -42,3 +42,14 @@ pub fn allocate_shm_file(size: i32) -> Result<OwnedFd, &'static str> {
         }
     }
 }
+
+// Grows or shrinks a region allocated with `allocate_shm_file`
+pub fn resize_shm_file(fd: RawFd, size: i32) -> Result<(), &'static str> {
+    let ret = unsafe { libc::ftruncate(fd, size.into()) };
+
+    if ret < 0 {
+        Err("Unable to resize shared memory region")
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::slice;
+
+use crate::{
+    arena::Arena,
+    wav::{decode_wav, Wav},
+};
+
+// A sound loaded into game memory, ready to be played by the mixer
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Sound {
+    // Interleaved f32 samples in an arena
+    samples: *const f32,
+    pub frame_count: usize,
+    pub num_channels: u8,
+    // What the sound was recorded at, it is resampled to the output rate as
+    // it plays
+    pub sample_rate: u32,
+}
+
+impl Sound {
+    // Loads the contents of a WAV file
+    pub fn load(bytes: &[u8], arena: &mut Arena) -> Result<Sound, &'static str> {
+        let wav = decode_wav(bytes)?;
+        Sound::from_wav(&wav, arena)
+    }
+
+    pub fn from_wav(wav: &Wav, arena: &mut Arena) -> Result<Sound, &'static str> {
+        let samples = arena
+            .push_slice(&wav.samples)
+            .ok_or("Not enough memory left for the sound")?;
+
+        Ok(Sound {
+            samples,
+            frame_count: wav.samples.len() / wav.num_channels as usize,
+            num_channels: wav.num_channels,
+            sample_rate: wav.sample_rate,
+        })
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        // Still there by the contract of `Arena::new`
+        let len = self.frame_count * self.num_channels as usize;
+        unsafe { slice::from_raw_parts(self.samples, len) }
+    }
+
+    // Left and right of a frame, mono sounds play the same on both
+    fn frame(&self, index: usize) -> [f32; 2] {
+        let samples = self.samples();
+        match self.num_channels {
+            1 => [samples[index]; 2],
+            _ => [samples[2 * index], samples[2 * index + 1]],
+        }
+    }
+}
+
+// Plays a sound at any output rate by stepping through it at the sound's own
+// rate and interpolating between its frames
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SamplePlayer {
+    pub sound: Sound,
+    // Start over at the end instead of finishing, e.g. for music
+    pub looping: bool,
+    // In frames of the sound, usually between two of them
+    position: f64,
+}
+
+impl SamplePlayer {
+    pub fn new(sound: Sound, looping: bool) -> SamplePlayer {
+        SamplePlayer {
+            sound,
+            looping,
+            position: 0.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.sound.frame_count as f64
+    }
+
+    // Advances by one frame at `sample_rate`, playing `pitch` times faster
+    // than the sound was recorded
+    pub fn next(&mut self, sample_rate: u32, pitch: f32) -> [f32; 2] {
+        let frame_count = self.sound.frame_count;
+        if self.is_finished() {
+            return [0.0; 2];
+        }
+
+        let index = self.position as usize;
+        let t = (self.position - index as f64) as f32;
+        let current = self.sound.frame(index);
+        // Past the last frame it either wraps around or goes quiet
+        let next = if index + 1 < frame_count {
+            self.sound.frame(index + 1)
+        } else if self.looping {
+            self.sound.frame(0)
+        } else {
+            [0.0; 2]
+        };
+
+        self.position += self.sound.sample_rate as f64 * pitch as f64 / sample_rate as f64;
+        if self.looping {
+            self.position %= frame_count as f64;
+        }
+
+        [
+            current[0] + (next[0] - current[0]) * t,
+            current[1] + (next[1] - current[1]) * t,
+        ]
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    fs::File,
+    os::fd::{AsFd, AsRawFd},
+};
+
+use wayland_client::{
+    protocol::{wl_buffer, wl_shm, wl_shm_pool, wl_surface},
+    Dispatch, QueueHandle,
+};
+
+use handmade_hero::BYTES_PER_PIXEL;
+
+use crate::shm;
+
+// Enough for one buffer on screen, one queued in the compositor and one for
+// the game to draw into
+pub const BUFFER_COUNT: usize = 3;
+
+// Where a buffer lives in the pool and how big it is
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Layout {
+    offset: i32,
+    width: i32,
+    height: i32,
+    stride: i32,
+}
+
+impl Layout {
+    fn end(&self) -> i32 {
+        self.offset + self.stride * self.height
+    }
+
+    fn overlaps(&self, other: &Layout) -> bool {
+        self.offset < other.end() && other.offset < self.end()
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    // Created the first time the slot is used at the current size
+    buffer: Option<wl_buffer::WlBuffer>,
+    layout: Option<Layout>,
+    // Attached to the surface and not released by the compositor yet. A
+    // busy slot keeps its buffer and its part of the pool, whatever size it
+    // is, until the release.
+    busy: bool,
+}
+
+// A fixed set of wl_buffers in a single shm pool. The game only ever draws
+// into a buffer the compositor has released, and the buffers are reused from
+// frame to frame until the window size changes.
+pub struct Swapchain {
+    file: File,
+    pool: wl_shm_pool::WlShmPool,
+    pool_size: i32,
+    data: memmap::MmapMut,
+    slots: [Slot; BUFFER_COUNT],
+    pub width: i32,
+    pub height: i32,
+    pub stride: i32,
+    // Buffer scale that goes with `width` and `height`. Only set on the
+    // surface along with a buffer of that size, the compositor may reject
+    // the scale otherwise.
+    pub scale: i32,
+}
+
+impl Swapchain {
+    pub fn new<D>(
+        shm: &wl_shm::WlShm,
+        width: i32,
+        height: i32,
+        qh: &QueueHandle<D>,
+    ) -> Result<Swapchain, &'static str>
+    where
+        D: Dispatch<wl_shm_pool::WlShmPool, ()> + 'static,
+    {
+        let (stride, buffer_size) = layout(width, height)?;
+        let pool_size = buffer_size * BUFFER_COUNT as i32;
+        let file = File::from(shm::allocate_shm_file(pool_size)?);
+        let data = unsafe { memmap::MmapOptions::new().map_mut(&file) }
+            .map_err(|_| "Unable to map backbuffer")?;
+        let pool = shm.create_pool(file.as_fd(), pool_size, qh, ());
+
+        Ok(Swapchain {
+            file,
+            pool,
+            pool_size,
+            data,
+            slots: Default::default(),
+            width,
+            height,
+            stride,
+            scale: 1,
+        })
+    }
+
+    // Buffers of the old size the compositor is done with are destroyed
+    // right away. The ones it still holds stay where they are until they are
+    // released, and are only then replaced by buffers of the new size.
+    pub fn resize(&mut self, width: i32, height: i32, scale: i32) -> Result<(), &'static str> {
+        if width == self.width && height == self.height {
+            self.scale = scale;
+            return Ok(());
+        }
+
+        let (stride, _) = layout(width, height)?;
+        for slot in self.slots.iter_mut().filter(|slot| !slot.busy) {
+            if let Some(buffer) = slot.buffer.take() {
+                buffer.destroy();
+            }
+            slot.layout = None;
+        }
+
+        self.width = width;
+        self.height = height;
+        self.stride = stride;
+        self.scale = scale;
+        Ok(())
+    }
+
+    // Index of a buffer of the current size the compositor is done with, or
+    // None if it still holds all of them
+    pub fn acquire<D>(&mut self, qh: &QueueHandle<D>) -> Option<usize>
+    where
+        D: Dispatch<wl_buffer::WlBuffer, usize> + 'static,
+    {
+        let (index, layout) = acquire_slot(&self.layouts(), self.width, self.height, self.stride)?;
+        if self.slots[index].layout == Some(layout) {
+            return Some(index);
+        }
+
+        if layout.end() > self.pool_size {
+            if let Err(e) = self.grow_pool(layout.end()) {
+                eprintln!("{}", e);
+                return None;
+            }
+        }
+
+        let slot = &mut self.slots[index];
+        if let Some(buffer) = slot.buffer.take() {
+            buffer.destroy();
+        }
+        slot.buffer = Some(self.pool.create_buffer(
+            layout.offset,
+            layout.width,
+            layout.height,
+            layout.stride,
+            wl_shm::Format::Xrgb8888,
+            qh,
+            index,
+        ));
+        slot.layout = Some(layout);
+
+        Some(index)
+    }
+
+    pub fn pixels(&mut self, index: usize) -> &mut [u8] {
+        let layout = self.slots[index].layout.unwrap();
+        &mut self.data[layout.offset as usize..layout.end() as usize]
+    }
+
+    // Hands the buffer over to the compositor until it is released
+    pub fn attach(&mut self, index: usize, surface: &wl_surface::WlSurface) {
+        let slot = &mut self.slots[index];
+        slot.busy = true;
+        surface.attach(slot.buffer.as_ref(), 0, 0);
+    }
+
+    pub fn release(&mut self, index: usize) {
+        self.slots[index].busy = false;
+    }
+
+    fn layouts(&self) -> [(Option<Layout>, bool); BUFFER_COUNT] {
+        let mut layouts = [(None, false); BUFFER_COUNT];
+        for (layout, slot) in layouts.iter_mut().zip(&self.slots) {
+            *layout = (slot.layout, slot.busy);
+        }
+        layouts
+    }
+
+    // The pool only ever grows since wl_shm_pool can't shrink
+    fn grow_pool(&mut self, size: i32) -> Result<(), &'static str> {
+        shm::resize_shm_file(self.file.as_raw_fd(), size)?;
+        self.pool.resize(size);
+
+        // Map the whole, larger file again. The old mapping goes away once
+        // it is replaced.
+        self.data = unsafe { memmap::MmapOptions::new().map_mut(&self.file) }
+            .map_err(|_| "Unable to map resized backbuffer")?;
+        self.pool_size = size;
+        Ok(())
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        for buffer in self.slots.iter_mut().filter_map(|slot| slot.buffer.take()) {
+            buffer.destroy();
+        }
+        self.pool.destroy();
+    }
+}
+
+// Picks a slot the compositor isn't holding, given each slot's buffer layout
+// and whether it is busy, and where its buffer of the wanted size goes. A
+// slot that already has such a buffer is preferred, otherwise the new buffer
+// is put at the lowest offset that doesn't overlap any other buffer.
+fn acquire_slot(
+    slots: &[(Option<Layout>, bool)],
+    width: i32,
+    height: i32,
+    stride: i32,
+) -> Option<(usize, Layout)> {
+    let fits = |index: usize| {
+        slots[index]
+            .0
+            .is_some_and(|l| l.width == width && l.height == height && l.stride == stride)
+    };
+    let free = |index: &usize| !slots[*index].1;
+
+    let index = (0..slots.len())
+        .filter(free)
+        .find(|&index| fits(index))
+        .or_else(|| (0..slots.len()).find(free))?;
+    if fits(index) {
+        return Some((index, slots[index].0.unwrap()));
+    }
+
+    // Other buffers stay where they are, try right after each of them
+    let others: Vec<Layout> = slots
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .filter_map(|(_, (layout, _))| *layout)
+        .collect();
+    let offset = std::iter::once(0)
+        .chain(others.iter().map(Layout::end))
+        .filter(|&offset| {
+            let layout = Layout {
+                offset,
+                width,
+                height,
+                stride,
+            };
+            !others.iter().any(|other| other.overlaps(&layout))
+        })
+        .min()?;
+
+    Some((
+        index,
+        Layout {
+            offset,
+            width,
+            height,
+            stride,
+        },
+    ))
+}
+
+// Stride and size of a single buffer at this size
+fn layout(width: i32, height: i32) -> Result<(i32, i32), &'static str> {
+    let stride = width
+        .checked_mul(BYTES_PER_PIXEL as i32)
+        .ok_or("Window size is too large for the backbuffer")?;
+    // Every slot at this size has to fit the pool
+    let buffer_size = stride
+        .checked_mul(height)
+        .filter(|size| size.checked_mul(BUFFER_COUNT as i32).is_some())
+        .ok_or("Window size is too large for the backbuffer")?;
+
+    Ok((stride, buffer_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays the compositor: attaches whatever is acquired and releases
+    // buffers in the order it was given them, checking that a held buffer is
+    // never handed out again or overlapped by a new one
+    struct Compositor {
+        slots: [(Option<Layout>, bool); BUFFER_COUNT],
+        held: Vec<usize>,
+    }
+
+    impl Compositor {
+        fn acquire(&mut self, width: i32, height: i32) -> Option<usize> {
+            let (index, layout) =
+                acquire_slot(&self.slots, width, height, width * BYTES_PER_PIXEL as i32)?;
+            assert!(!self.slots[index].1, "slot {} is still held", index);
+            for (other, (held, busy)) in self.slots.iter().enumerate() {
+                if other != index && *busy {
+                    assert!(
+                        !held.unwrap().overlaps(&layout),
+                        "overlaps held slot {}",
+                        other
+                    );
+                }
+            }
+
+            self.slots[index] = (Some(layout), true);
+            self.held.push(index);
+            Some(index)
+        }
+
+        fn release_oldest(&mut self) {
+            let index = self.held.remove(0);
+            self.slots[index].1 = false;
+        }
+    }
+
+    #[test]
+    fn never_hands_out_held_buffers() {
+        let mut compositor = Compositor {
+            slots: [(None, false); BUFFER_COUNT],
+            held: Vec::new(),
+        };
+
+        // Resize on every frame while the compositor holds on to two buffers
+        let sizes = [
+            (100, 50),
+            (120, 60),
+            (80, 40),
+            (300, 200),
+            (300, 200),
+            (10, 10),
+        ];
+        for (frame, &(width, height)) in sizes.iter().cycle().take(50).enumerate() {
+            compositor.acquire(width, height).unwrap();
+            if frame >= 1 {
+                compositor.release_oldest();
+            }
+        }
+
+        // With every buffer held there is nothing to draw into
+        while compositor.acquire(64, 64).is_some() {}
+        assert_eq!(compositor.held.len(), BUFFER_COUNT);
+    }
+
+    #[test]
+    fn reuses_buffers_of_the_same_size() {
+        let mut slots = [(None, false); BUFFER_COUNT];
+        let (index, layout) = acquire_slot(&slots, 100, 50, 400).unwrap();
+        slots[index] = (Some(layout), false);
+
+        assert_eq!(acquire_slot(&slots, 100, 50, 400), Some((index, layout)));
+    }
+}
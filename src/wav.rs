@@ -0,0 +1,120 @@
+// RIFF WAV decoding. Handles integer PCM of 8, 16, 24 and 32 bits and IEEE
+// float of 32 and 64 bits, mono or stereo, at any sample rate.
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+// The real format is then in the first two bytes of the sub-format GUID
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+pub struct Wav {
+    pub sample_rate: u32,
+    pub num_channels: u8,
+    // Interleaved, in [-1, 1]
+    pub samples: Vec<f32>,
+}
+
+#[derive(Clone, Copy)]
+struct Format {
+    tag: u16,
+    num_channels: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits_per_sample: u16,
+}
+
+pub fn decode_wav(bytes: &[u8]) -> Result<Wav, &'static str> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a RIFF WAV file");
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut chunks = &bytes[12..];
+    while chunks.len() >= 8 {
+        let id = &chunks[0..4];
+        let size = read_u32(chunks, 4) as usize;
+        let body = &chunks[8..];
+        // Streamed files may not know their final size, take whatever is there
+        let body = &body[..size.min(body.len())];
+
+        match id {
+            b"fmt " => format = Some(decode_format(body)?),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        let next = 8 + size + (size & 1);
+        chunks = chunks.get(next..).unwrap_or(&[]);
+    }
+
+    let format = format.ok_or("WAV file has no format chunk")?;
+    let data = data.ok_or("WAV file has no data chunk")?;
+
+    let num_channels = match format.num_channels {
+        1 | 2 => format.num_channels as u8,
+        _ => return Err("Only mono and stereo WAV files are supported"),
+    };
+    if format.sample_rate == 0 {
+        return Err("WAV file has a sample rate of 0");
+    }
+
+    let bytes_per_sample = (format.bits_per_sample as usize).div_ceil(8);
+    if format.block_align as usize != bytes_per_sample * num_channels as usize {
+        return Err("WAV file has an inconsistent block size");
+    }
+
+    let decode: fn(&[u8]) -> f32 = match (format.tag, format.bits_per_sample) {
+        (FORMAT_PCM, 8) => |s| (s[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 16) => |s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+        // Shifted up into the top of an i32 to sign extend
+        (FORMAT_PCM, 24) => |s| i32::from_le_bytes([0, s[0], s[1], s[2]]) as f32 / 2147483648.0,
+        (FORMAT_PCM, 32) => |s| i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2147483648.0,
+        (FORMAT_IEEE_FLOAT, 32) => |s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+        (FORMAT_IEEE_FLOAT, 64) => |s| f64::from_le_bytes(s.try_into().unwrap()) as f32,
+        (FORMAT_PCM | FORMAT_IEEE_FLOAT, _) => return Err("Unsupported WAV sample size"),
+        _ => return Err("Unsupported WAV sample format"),
+    };
+
+    // A partial frame at the end is dropped
+    let frames = data.len() / format.block_align as usize;
+    let samples = data[..frames * format.block_align as usize]
+        .chunks_exact(bytes_per_sample)
+        .map(decode)
+        .collect();
+
+    Ok(Wav {
+        sample_rate: format.sample_rate,
+        num_channels,
+        samples,
+    })
+}
+
+fn decode_format(body: &[u8]) -> Result<Format, &'static str> {
+    if body.len() < 16 {
+        return Err("WAV format chunk is too short");
+    }
+
+    let mut tag = read_u16(body, 0);
+    if tag == FORMAT_EXTENSIBLE {
+        if body.len() < 26 {
+            return Err("WAV format chunk is too short");
+        }
+        tag = read_u16(body, 24);
+    }
+
+    Ok(Format {
+        tag,
+        num_channels: read_u16(body, 2),
+        sample_rate: read_u32(body, 4),
+        block_align: read_u16(body, 12),
+        bits_per_sample: read_u16(body, 14),
+    })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,279 @@
+// Runs the game's audio through the headless backend and checks the signal:
+// frequency, amplitude, channel interleaving and that there are no clicks,
+// i.e. jumps between consecutive samples larger than the tone can produce.
+use std::f32::consts::PI;
+
+use std::{mem::size_of, path::Path};
+
+use handmade_hero::{Action, Mixer, Oscillator, SoundBuffer, Source, Waveform, MAX_VOICES};
+
+mod common;
+
+use common::Headless;
+
+const SAMPLE_RATE: u32 = 48000;
+const DT: f32 = 1.0 / 120.0;
+// What the game plays with the pitch untouched
+const BASE_TONE_HZ: f32 = 500.0;
+const AMPLITUDE: f32 = 0.7;
+
+fn headless(num_channels: u8) -> Headless {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    Headless::new(16, 16, SAMPLE_RATE, num_channels, &assets)
+}
+
+// One channel out of interleaved samples
+fn channel(samples: &[f32], num_channels: usize, channel: usize) -> Vec<f32> {
+    samples
+        .iter()
+        .skip(channel)
+        .step_by(num_channels)
+        .copied()
+        .collect()
+}
+
+// Estimates the frequency from the time between the first and last upward
+// zero crossing, interpolating where exactly each crossing happened
+fn frequency(samples: &[f32]) -> f32 {
+    let crossings: Vec<f32> = samples
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+        .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+        .collect();
+    assert!(crossings.len() >= 2, "signal has no periods to measure");
+
+    let periods = (crossings.len() - 1) as f32;
+    let seconds = (crossings[crossings.len() - 1] - crossings[0]) / SAMPLE_RATE as f32;
+    periods / seconds
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, s| f32::max(peak, s.abs()))
+}
+
+// Index and size of the largest jump between consecutive samples
+fn largest_jump(samples: &[f32]) -> (usize, f32) {
+    samples
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .enumerate()
+        .fold((0, 0.0), |largest, (i, jump)| {
+            if jump > largest.1 {
+                (i + 1, jump)
+            } else {
+                largest
+            }
+        })
+}
+
+// The steepest a sine of this frequency and amplitude gets between two
+// samples, with some room for rounding
+fn max_step(tone_hz: f32, amplitude: f32) -> f32 {
+    2.0 * PI * tone_hz * amplitude / SAMPLE_RATE as f32 * 1.05
+}
+
+fn assert_no_clicks(samples: &[f32], tone_hz: f32) {
+    let (index, jump) = largest_jump(samples);
+    let allowed = max_step(tone_hz, AMPLITUDE);
+    assert!(
+        jump <= allowed,
+        "click at sample {}: jumped by {} where a {} Hz tone moves at most {}",
+        index,
+        jump,
+        tone_hz,
+        allowed
+    );
+}
+
+#[test]
+fn plays_base_tone() {
+    let mut headless = headless(1);
+    let samples = headless.play_sound(SAMPLE_RATE as usize);
+
+    let measured = frequency(&samples);
+    assert!(
+        (measured - BASE_TONE_HZ).abs() < BASE_TONE_HZ * 0.005,
+        "expected {} Hz, measured {} Hz",
+        BASE_TONE_HZ,
+        measured
+    );
+}
+
+#[test]
+fn plays_at_expected_amplitude() {
+    let mut headless = headless(1);
+    let samples = headless.play_sound(SAMPLE_RATE as usize / 10);
+
+    let peak = peak(&samples);
+    assert!(
+        (AMPLITUDE * 0.99..=AMPLITUDE + 1e-4).contains(&peak),
+        "expected a peak of {}, got {}",
+        AMPLITUDE,
+        peak
+    );
+}
+
+#[test]
+fn interleaves_channels() {
+    let mut headless = headless(2);
+    let samples = headless.play_sound(SAMPLE_RATE as usize / 10);
+    assert_eq!(samples.len(), SAMPLE_RATE as usize / 10 * 2);
+
+    let left = channel(&samples, 2, 0);
+    let right = channel(&samples, 2, 1);
+    assert_eq!(left, right, "the tone should be the same on both channels");
+    assert!(
+        (frequency(&left) - BASE_TONE_HZ).abs() < BASE_TONE_HZ * 0.005,
+        "interleaving changed the tone"
+    );
+}
+
+#[test]
+fn continuous_across_buffers() {
+    // PulseAudio asks for whatever it likes, so use awkward sizes
+    let mut headless = headless(2);
+    let mut samples = Vec::new();
+    for frames in [37, 480, 1, 1023, 4096, 5, 800].iter().cycle().take(40) {
+        samples.extend(headless.play_sound(*frames));
+    }
+
+    let left = channel(&samples, 2, 0);
+    assert_no_clicks(&left, BASE_TONE_HZ);
+    assert_no_clicks(&channel(&samples, 2, 1), BASE_TONE_HZ);
+}
+
+#[test]
+fn continuous_across_pitch_change() {
+    let mut headless = headless(1);
+    let mut samples = headless.play_sound(SAMPLE_RATE as usize / 2);
+
+    // Moving right bends the pitch up by PITCH_SPEED per second
+    headless.set_action(Action::MoveRight, true);
+    for _ in 0..60 {
+        headless.update(DT);
+        samples.extend(headless.play_sound(SAMPLE_RATE as usize / 120));
+    }
+    headless.set_action(Action::MoveRight, false);
+    headless.update(DT);
+    samples.extend(headless.play_sound(SAMPLE_RATE as usize / 2));
+
+    // The pitch only ever went up, so the highest tone bounds every step
+    let highest_hz = frequency(&samples[samples.len() - SAMPLE_RATE as usize / 4..]);
+    assert!(
+        highest_hz > BASE_TONE_HZ,
+        "moving right should raise the pitch"
+    );
+    assert_no_clicks(&samples, highest_hz);
+}
+
+#[test]
+fn waveforms_keep_frequency_and_amplitude() {
+    for waveform in Waveform::ALL {
+        let mut oscillator = Oscillator::new(waveform, 220.0, 0.5);
+        let samples: Vec<f32> = (0..SAMPLE_RATE)
+            .map(|_| oscillator.next(SAMPLE_RATE))
+            .collect();
+
+        assert!(peak(&samples) <= 0.5 + 1e-6, "{:?} is too loud", waveform);
+        if waveform != Waveform::Noise {
+            let measured = frequency(&samples);
+            assert!(
+                (measured - 220.0).abs() < 220.0 * 0.005,
+                "{:?}: expected 220 Hz, measured {} Hz",
+                waveform,
+                measured
+            );
+        }
+    }
+}
+
+#[test]
+fn glides_to_new_frequency() {
+    let mut oscillator = Oscillator::new(Waveform::Sine, 300.0, AMPLITUDE);
+    oscillator.set_frequency(900.0);
+
+    let samples: Vec<f32> = (0..SAMPLE_RATE / 2)
+        .map(|_| oscillator.next(SAMPLE_RATE))
+        .collect();
+    assert_no_clicks(&samples, 900.0);
+    assert!((oscillator.frequency() - 900.0).abs() < 0.01);
+}
+
+fn sine(frequency: f32, amplitude: f32) -> Source {
+    Source::Oscillator(Oscillator::new(Waveform::Sine, frequency, amplitude))
+}
+
+// Runs the mixer on its own, without the game, for `frames` stereo frames
+fn mix(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+    let mut data = vec![0u8; frames * 2 * size_of::<f32>()];
+    mixer.mix(&mut SoundBuffer {
+        data: &mut data,
+        bytes_per_sample: size_of::<f32>(),
+        sample_rate: SAMPLE_RATE,
+        num_channels: 2,
+    });
+
+    data.chunks_exact(size_of::<f32>())
+        .map(|sample| f32::from_ne_bytes(sample.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn mixer_pans_and_scales_voices() {
+    let mut mixer = Mixer::new();
+    mixer.play(sine(440.0, 0.8), 0.5, -1.0, 0.0).unwrap();
+    let samples = mix(&mut mixer, SAMPLE_RATE as usize / 10);
+
+    let left = channel(&samples, 2, 0);
+    let right = channel(&samples, 2, 1);
+    assert!(
+        (peak(&left) - 0.4).abs() < 1e-3,
+        "left peak {}",
+        peak(&left)
+    );
+    assert_eq!(peak(&right), 0.0, "a hard left voice leaked to the right");
+}
+
+#[test]
+fn mixer_fades_voices_in_and_out() {
+    let mut mixer = Mixer::new();
+    let voice = mixer.play(sine(440.0, 0.5), 1.0, 0.0, 0.1).unwrap();
+
+    // Half way through the fade in the voice is at about half volume
+    let fading_in = channel(&mix(&mut mixer, SAMPLE_RATE as usize / 20), 2, 0);
+    assert!(peak(&fading_in[..100]) < 0.01, "fade in started loud");
+    let faded_in = channel(&mix(&mut mixer, SAMPLE_RATE as usize / 10), 2, 0);
+    assert!((peak(&faded_in) - 0.5).abs() < 1e-3);
+
+    mixer.stop(voice, 0.05);
+    assert!(mixer.is_playing(voice), "voice stopped before fading out");
+    let fading_out = channel(&mix(&mut mixer, SAMPLE_RATE as usize / 10), 2, 0);
+    assert_no_clicks(&fading_out, 440.0);
+    assert!(
+        !mixer.is_playing(voice),
+        "voice kept playing after fading out"
+    );
+    assert_eq!(peak(&fading_out[SAMPLE_RATE as usize / 20..]), 0.0);
+}
+
+#[test]
+fn mixer_keeps_many_voices_within_range() {
+    let mut mixer = Mixer::new();
+    for voice in 0..MAX_VOICES {
+        assert!(mixer
+            .play(sine(100.0 + voice as f32, 1.0), 1.0, 0.0, 0.0)
+            .is_some());
+    }
+    assert!(
+        mixer.play(sine(100.0, 1.0), 1.0, 0.0, 0.0).is_none(),
+        "played more voices than there are"
+    );
+
+    let samples = mix(&mut mixer, SAMPLE_RATE as usize / 10);
+    assert!(
+        peak(&samples) <= 1.0,
+        "mixed {} past full scale",
+        peak(&samples)
+    );
+}
@@ -0,0 +1,174 @@
+// Decodes BMP and PNG images and draws them into pixel buffers, checking
+// blending, clipping and that padding past each row is left alone.
+use std::path::Path;
+
+use handmade_hero::{decode_image, Arena, Bitmap, Image, PixelBuffer};
+
+// Written into row padding, drawing must never touch it
+const PADDING: u8 = 0xaa;
+
+// A BMP file with a BITMAPINFOHEADER, followed by bit field masks when given
+fn bmp_bytes(width: i32, height: i32, bits: u16, masks: Option<[u32; 3]>, data: &[u8]) -> Vec<u8> {
+    let masks_size = if masks.is_some() { 12 } else { 0 };
+    let pixel_offset = 14 + 40 + masks_size;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"BM");
+    bytes.extend_from_slice(&(pixel_offset + data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&pixel_offset.to_le_bytes());
+    bytes.extend_from_slice(&40u32.to_le_bytes());
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&bits.to_le_bytes());
+    let compression: u32 = if masks.is_some() { 3 } else { 0 };
+    bytes.extend_from_slice(&compression.to_le_bytes());
+    bytes.extend_from_slice(&[0; 20]);
+    for mask in masks.iter().flatten() {
+        bytes.extend_from_slice(&mask.to_le_bytes());
+    }
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn bitmap(image: &Image, memory: &mut Vec<u64>) -> Bitmap {
+    memory.resize(image.pixels.len(), 0);
+    // `memory` outlives everything allocated from the arena
+    let mut arena = unsafe { Arena::new(memory.as_mut_ptr() as *mut u8, memory.len() * 8) };
+    Bitmap::from_image(image, &mut arena).unwrap()
+}
+
+fn solid(width: i32, height: i32, color: u32) -> Image {
+    Image {
+        width,
+        height,
+        pixels: vec![color; (width * height) as usize],
+    }
+}
+
+// A `width` x `height` Xrgb8888 buffer with a pixel of padding at the end of
+// every row
+fn buffer_data(width: i32, height: i32, color: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    for _ in 0..height {
+        for _ in 0..width {
+            data.extend_from_slice(&color.to_le_bytes());
+        }
+        data.extend_from_slice(&[PADDING; 4]);
+    }
+    data
+}
+
+fn pixel_at(data: &[u8], width: i32, x: i32, y: i32) -> u32 {
+    let pixel = ((y * (width + 1) + x) * 4) as usize;
+    u32::from_le_bytes(data[pixel..pixel + 4].try_into().unwrap()) & 0xff_ffff
+}
+
+#[test]
+fn decodes_24_bit_bottom_up_bmp() {
+    // Two rows of three pixels, each row padded from 9 to 12 bytes. The
+    // bottom row comes first.
+    #[rustfmt::skip]
+    let data = [
+        0, 0, 255,  0, 255, 0,  255, 0, 0,  0, 0, 0,
+        255, 255, 255,  0, 0, 0,  128, 128, 128,  0, 0, 0,
+    ];
+    let image = decode_image(&bmp_bytes(3, 2, 24, None, &data)).unwrap();
+
+    assert_eq!((image.width, image.height), (3, 2));
+    assert_eq!(
+        image.pixels,
+        [0xffffffff, 0xff000000, 0xff808080, 0xffff0000, 0xff00ff00, 0xff0000ff]
+    );
+}
+
+#[test]
+fn decodes_32_bit_top_down_bmp_with_masks() {
+    // Rgba in memory order with alpha in the top byte, one opaque and one
+    // half transparent pixel
+    let masks = Some([0x0000_00ff, 0x0000_ff00, 0x00ff_0000]);
+    let data = [255, 0, 0, 255, 255, 255, 255, 128];
+    let image = decode_image(&bmp_bytes(2, -1, 32, masks, &data)).unwrap();
+
+    // Without a mask for it, alpha is ignored
+    assert_eq!(image.pixels, [0xffff0000, 0xffffffff]);
+}
+
+#[test]
+fn rejects_broken_bmp() {
+    assert!(decode_image(b"BM").is_err());
+    assert!(decode_image(&bmp_bytes(3, 2, 8, None, &[0; 8])).is_err());
+    assert!(decode_image(&bmp_bytes(3, 2, 24, None, &[0; 12])).is_err());
+    assert!(decode_image(&bmp_bytes(0, 2, 24, None, &[])).is_err());
+}
+
+#[test]
+fn decodes_png_with_alpha() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/hero.png");
+    let image = decode_image(&std::fs::read(path).unwrap()).unwrap();
+
+    assert_eq!((image.width, image.height), (48, 48));
+    // The corners are see-through, the middle is solid
+    assert_eq!(image.pixels[0], 0);
+    assert_eq!(image.pixels[24 * 48 + 24] >> 24, 0xff);
+    // Premultiplied, no channel is brighter than the alpha
+    for pixel in &image.pixels {
+        let alpha = pixel >> 24;
+        assert!([16, 8, 0]
+            .iter()
+            .all(|shift| (pixel >> shift) & 0xff <= alpha));
+    }
+}
+
+#[test]
+fn blends_by_alpha() {
+    let mut memory = Vec::new();
+    // White at half coverage, premultiplied
+    let half_white = bitmap(&solid(1, 1, 0x80808080), &mut memory);
+    let mut data = buffer_data(2, 1, 0x000000ff);
+    let mut buffer = PixelBuffer {
+        data: &mut data,
+        width: 2,
+        height: 1,
+        stride: 3 * 4,
+    };
+
+    buffer.draw_bitmap(&half_white, 0, 0);
+    assert_eq!(pixel_at(&data, 2, 0, 0), 0x8080ff);
+    assert_eq!(pixel_at(&data, 2, 1, 0), 0x0000ff);
+}
+
+#[test]
+fn clips_to_buffer() {
+    let mut memory = Vec::new();
+    let red = bitmap(&solid(4, 4, 0xffff0000), &mut memory);
+    let (width, height) = (6, 5);
+
+    for (x, y) in [(-2, -2), (4, 3), (-3, 2), (3, -3), (-10, 0), (0, 10)] {
+        let mut data = buffer_data(width, height, 0);
+        let mut buffer = PixelBuffer {
+            data: &mut data,
+            width,
+            height,
+            stride: (width + 1) * 4,
+        };
+        buffer.draw_bitmap(&red, x, y);
+
+        for py in 0..height {
+            for px in 0..width {
+                let inside = (x..x + 4).contains(&px) && (y..y + 4).contains(&py);
+                let expected = if inside { 0xff0000 } else { 0 };
+                assert_eq!(
+                    pixel_at(&data, width, px, py),
+                    expected,
+                    "at {}, {}",
+                    px,
+                    py
+                );
+            }
+            let padding = ((py * (width + 1) + width) * 4) as usize;
+            assert_eq!(data[padding..padding + 4], [PADDING; 4]);
+        }
+    }
+}
@@ -0,0 +1,8 @@
+// The headless backend of src/bin/headless, shared by the tests that drive the
+// game or write its output. Not every test uses all of it.
+#![allow(dead_code)]
+
+#[path = "../../src/bin/headless/backend.rs"]
+mod backend;
+
+pub use backend::*;
@@ -0,0 +1,199 @@
+// Draws every primitive into buffers with padded rows, checking which pixels
+// they cover, that clipping holds for shapes far off screen, and that the
+// padding after each row is never written.
+use handmade_hero::{PixelBuffer, BYTES_PER_PIXEL};
+
+const WIDTH: i32 = 16;
+const HEIGHT: i32 = 12;
+// Extra pixels at the end of every row
+const PADDING_PIXELS: i32 = 3;
+const PADDING: u8 = 0xaa;
+const WHITE: u32 = 0xffffff;
+
+struct Canvas {
+    data: Vec<u8>,
+}
+
+impl Canvas {
+    fn new() -> Canvas {
+        let stride = ((WIDTH + PADDING_PIXELS) * BYTES_PER_PIXEL as i32) as usize;
+        let mut data = vec![PADDING; stride * HEIGHT as usize];
+        for row in data.chunks_exact_mut(stride) {
+            row[..WIDTH as usize * BYTES_PER_PIXEL].fill(0);
+        }
+        Canvas { data }
+    }
+
+    fn draw(&mut self, draw: impl FnOnce(&mut PixelBuffer)) {
+        let mut buffer = PixelBuffer {
+            data: &mut self.data,
+            width: WIDTH,
+            height: HEIGHT,
+            stride: (WIDTH + PADDING_PIXELS) * BYTES_PER_PIXEL as i32,
+        };
+        draw(&mut buffer);
+        self.assert_padding_untouched();
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> u32 {
+        let offset = ((y * (WIDTH + PADDING_PIXELS) + x) * BYTES_PER_PIXEL as i32) as usize;
+        u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap()) & 0xff_ffff
+    }
+
+    // Coordinates of every pixel that isn't black
+    fn covered(&self) -> Vec<(i32, i32)> {
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.pixel(x, y) != 0)
+            .collect()
+    }
+
+    fn assert_padding_untouched(&self) {
+        let stride = ((WIDTH + PADDING_PIXELS) * BYTES_PER_PIXEL as i32) as usize;
+        for (y, row) in self.data.chunks_exact(stride).enumerate() {
+            assert!(
+                row[WIDTH as usize * BYTES_PER_PIXEL..]
+                    .iter()
+                    .all(|&b| b == PADDING),
+                "padding of row {} was written",
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn fills_and_outlines_rects() {
+    let mut canvas = Canvas::new();
+    canvas.draw(|buffer| buffer.fill_rect(-2, 10, 5, 10, WHITE));
+    let expected: Vec<_> = (10..12).flat_map(|y| (0..3).map(move |x| (x, y))).collect();
+    assert_eq!(canvas.covered(), expected);
+
+    let mut canvas = Canvas::new();
+    canvas.draw(|buffer| buffer.draw_rect(1, 1, 4, 3, WHITE));
+    #[rustfmt::skip]
+    let expected = [
+        (1, 1), (2, 1), (3, 1), (4, 1),
+        (1, 2), (4, 2),
+        (1, 3), (2, 3), (3, 3), (4, 3),
+    ];
+    assert_eq!(canvas.covered(), expected);
+}
+
+#[test]
+fn draws_lines_end_to_end() {
+    let mut canvas = Canvas::new();
+    canvas.draw(|buffer| buffer.draw_line(2, 1, 12, 5, WHITE));
+    let covered = canvas.covered();
+
+    // One pixel per column, each next to the one before
+    assert_eq!(covered.len(), 11);
+    assert!(covered.contains(&(2, 1)) && covered.contains(&(12, 5)));
+    let mut by_column = covered.clone();
+    by_column.sort();
+    for pair in by_column.windows(2) {
+        assert_eq!(pair[1].0 - pair[0].0, 1);
+        assert!((pair[1].1 - pair[0].1).abs() <= 1);
+    }
+}
+
+#[test]
+fn anti_aliases_lines() {
+    let mut canvas = Canvas::new();
+    // Half way between two rows, so both get half
+    canvas.draw(|buffer| buffer.draw_line_aa(2.5, 5.0, 12.5, 5.0, WHITE));
+    for x in 3..12 {
+        assert_eq!(canvas.pixel(x, 4), 0x808080, "above at {}", x);
+        assert_eq!(canvas.pixel(x, 5), 0x808080, "below at {}", x);
+    }
+
+    let mut canvas = Canvas::new();
+    // Through pixel centers, so only one row
+    canvas.draw(|buffer| buffer.draw_line_aa(2.5, 5.5, 12.5, 5.5, WHITE));
+    for x in 3..12 {
+        assert_eq!(canvas.pixel(x, 5), WHITE);
+    }
+    assert!(canvas.covered().iter().all(|&(_, y)| y == 5));
+}
+
+#[test]
+fn draws_circles() {
+    let mut canvas = Canvas::new();
+    canvas.draw(|buffer| buffer.fill_circle(6, 6, 3, WHITE));
+    let filled = canvas.covered();
+    for &(x, y) in &filled {
+        // Symmetric around the center
+        for (mx, my) in [(12 - x, y), (x, 12 - y), (12 - x, 12 - y)] {
+            assert!(filled.contains(&(mx, my)), "{}, {} has no mirror", x, y);
+        }
+    }
+    assert!(filled.contains(&(6, 3)) && filled.contains(&(9, 6)));
+    assert!(!filled.contains(&(3, 3)));
+
+    let mut outline = Canvas::new();
+    outline.draw(|buffer| buffer.draw_circle(6, 6, 3, WHITE));
+    let outline = outline.covered();
+    assert!(outline.contains(&(6, 3)) && outline.contains(&(3, 6)));
+    assert!(!outline.contains(&(6, 6)));
+    assert!(outline.iter().all(|pixel| filled.contains(pixel)));
+}
+
+#[test]
+fn fills_triangles_without_overlap() {
+    // Two triangles sharing the diagonal of a square, together they cover
+    // it exactly once. The edges run through pixel centers, which is where
+    // the fill rule matters.
+    let (a, b, c, d) = ((1.5, 1.5), (11.5, 1.5), (11.5, 9.5), (1.5, 9.5));
+    let mut first = Canvas::new();
+    first.draw(|buffer| buffer.fill_triangle([a, b, c], [WHITE; 3]));
+    let mut second = Canvas::new();
+    // Wound the other way round
+    second.draw(|buffer| buffer.fill_triangle([a, d, c], [WHITE; 3]));
+
+    let first = first.covered();
+    let second = second.covered();
+    assert!(first.iter().all(|pixel| !second.contains(pixel)));
+    assert_eq!(first.len() + second.len(), 10 * 8);
+}
+
+#[test]
+fn interpolates_triangle_colors() {
+    let mut canvas = Canvas::new();
+    let vertices = [(0.0, 0.0), (32.0, 0.0), (0.0, 24.0)];
+    canvas.draw(|buffer| buffer.fill_triangle(vertices, [0xff0000, 0x00ff00, 0x0000ff]));
+
+    // Near each vertex its color dominates
+    assert!(canvas.pixel(0, 0) >> 16 > 0xf0);
+    let right = canvas.pixel(15, 0);
+    assert!((right >> 8) & 0xff > 0x70 && right >> 16 > 0x70 && right & 0xff < 0x10);
+    // The weights always add up to one
+    for (x, y) in canvas.covered() {
+        let pixel = canvas.pixel(x, y);
+        let sum = (pixel >> 16) + ((pixel >> 8) & 0xff) + (pixel & 0xff);
+        assert!((254..=256).contains(&sum), "{:06x} at {}, {}", pixel, x, y);
+    }
+}
+
+#[test]
+fn clips_shapes_far_off_screen() {
+    let mut canvas = Canvas::new();
+    canvas.draw(|buffer| {
+        buffer.fill_rect(i32::MIN, i32::MIN, i32::MAX, i32::MAX, WHITE);
+        buffer.draw_rect(i32::MAX - 1, 0, 10, 10, WHITE);
+        buffer.draw_line(-1_000_000, -1_000_000, 1_000_000, 1_000_000, WHITE);
+        buffer.draw_line_aa(-1e9, 3.0, 1e9, 3.0, WHITE);
+        buffer.fill_circle(i32::MAX, i32::MAX, i32::MAX, WHITE);
+        buffer.draw_circle(i32::MIN, 0, 100, WHITE);
+        // Around the whole buffer, and with the edge running across it
+        buffer.draw_circle(0, 0, i32::MAX, WHITE);
+        buffer.draw_circle(-(1 << 30), 5, (1 << 30) + 12, WHITE);
+        buffer.fill_triangle([(-1e30, 0.0), (1e30, 0.0), (0.0, 1e30)], [WHITE; 3]);
+        buffer.fill_triangle([(f32::NAN, 0.0), (1.0, 0.0), (0.0, 1.0)], [WHITE; 3]);
+    });
+
+    // The diagonal line made it on
+    assert_eq!(canvas.pixel(5, 5), WHITE);
+    // So did the edge of the huge circle
+    assert_eq!(canvas.pixel(12, 0), WHITE);
+    assert_eq!(canvas.pixel(11, 0), 0);
+}
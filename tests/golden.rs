@@ -0,0 +1,177 @@
+// Renders known game states and compares them against reference images in
+// tests/golden. Run with UPDATE_GOLDEN=1 to (re)write the references after an
+// intended change to rendering, and check the new images in.
+//
+// On a mismatch the rendered image and a diff, with differing pixels in red,
+// are written to target/golden so they can be inspected.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use handmade_hero::Action;
+
+mod common;
+
+use common::{write_ppm, Headless};
+
+// Small enough that the references stay cheap to keep in the repository
+const WIDTH: i32 = 160;
+const HEIGHT: i32 = 90;
+const SAMPLE_RATE: u32 = 48000;
+const NUM_CHANNELS: u8 = 2;
+const DT: f32 = 1.0 / 120.0;
+// Largest per-channel difference still considered equal
+const TOLERANCE: u8 = 2;
+
+fn headless() -> Headless {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    Headless::new(WIDTH, HEIGHT, SAMPLE_RATE, NUM_CHANNELS, &assets)
+}
+
+// Holds `action` down for `updates` updates and lets go again
+fn hold(headless: &mut Headless, action: Action, updates: u32) {
+    headless.set_action(action, true);
+    for _ in 0..updates {
+        headless.update(DT);
+    }
+    headless.set_action(action, false);
+    headless.update(DT);
+}
+
+#[test]
+fn initial_frame() {
+    let mut headless = headless();
+    headless.update(DT);
+    assert_golden("initial_frame", &mut headless);
+}
+
+#[test]
+fn scrolled_right() {
+    let mut headless = headless();
+    hold(&mut headless, Action::MoveRight, 7);
+    assert_golden("scrolled_right", &mut headless);
+}
+
+#[test]
+fn scrolled_down() {
+    let mut headless = headless();
+    hold(&mut headless, Action::MoveDown, 5);
+    assert_golden("scrolled_down", &mut headless);
+}
+
+#[test]
+fn scrolled_past_wrap() {
+    // Offsets wrap at 256, far enough to wrap both axes
+    let mut headless = headless();
+    hold(&mut headless, Action::MoveLeft, 30);
+    hold(&mut headless, Action::MoveUp, 25);
+    assert_golden("scrolled_past_wrap", &mut headless);
+}
+
+fn assert_golden(name: &str, headless: &mut Headless) {
+    headless.render();
+    let actual = xrgb_to_rgb(&headless.pixels);
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.ppm", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        write_ppm(&reference_path, &headless.pixels, WIDTH, HEIGHT).unwrap();
+        return;
+    }
+
+    let (width, height, expected) = read_ppm(&reference_path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {}, run with UPDATE_GOLDEN=1 to create it",
+            reference_path.display(),
+            e
+        )
+    });
+    assert_eq!(
+        (width, height),
+        (WIDTH, HEIGHT),
+        "{}: reference has a different size",
+        name
+    );
+
+    let mut diff = Vec::with_capacity(actual.len() / 3 * 4);
+    let mut mismatches = 0;
+    for (actual, expected) in actual.chunks_exact(3).zip(expected.chunks_exact(3)) {
+        let matches = actual
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| a.abs_diff(*e) <= TOLERANCE);
+
+        // Xrgb8888 like the game draws, so it can go through write_ppm
+        if matches {
+            let gray = (actual.iter().map(|&c| c as u32).sum::<u32>() / 6) as u8;
+            diff.extend_from_slice(&[gray, gray, gray, 0]);
+        } else {
+            mismatches += 1;
+            diff.extend_from_slice(&[0, 0, 0xff, 0]);
+        }
+    }
+
+    if mismatches > 0 {
+        let out = output_dir();
+        let actual_path = out.join(format!("{}.actual.ppm", name));
+        let diff_path = out.join(format!("{}.diff.ppm", name));
+        write_ppm(&actual_path, &headless.pixels, WIDTH, HEIGHT).unwrap();
+        write_ppm(&diff_path, &diff, WIDTH, HEIGHT).unwrap();
+        panic!(
+            "{}: {} of {} pixels differ from {}, see {} and {}",
+            name,
+            mismatches,
+            WIDTH * HEIGHT,
+            reference_path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn output_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden");
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn xrgb_to_rgb(pixels: &[u8]) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+        .collect()
+}
+
+// Reads the binary PPMs written by `write_ppm`
+fn read_ppm(path: &Path) -> Result<(i32, i32, Vec<u8>), String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+
+    // Header is four whitespace separated fields: P6, width, height, maxval
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while fields.len() < 4 && i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            if i > start {
+                fields.push(String::from_utf8_lossy(&bytes[start..i]).into_owned());
+            }
+            start = i + 1;
+        }
+        i += 1;
+    }
+
+    match fields.as_slice() {
+        [magic, width, height, maxval] if magic == "P6" && maxval == "255" => {
+            let width: i32 = width.parse().map_err(|_| "bad width")?;
+            let height: i32 = height.parse().map_err(|_| "bad height")?;
+            let data = bytes[i..].to_vec();
+            if data.len() != (width * height * 3) as usize {
+                return Err("truncated image".to_string());
+            }
+            Ok((width, height, data))
+        }
+        _ => Err("not a binary 8-bit PPM".to_string()),
+    }
+}
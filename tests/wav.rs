@@ -0,0 +1,180 @@
+// Decodes WAV files of every supported format and plays loaded sounds through
+// the mixer at a different rate than they were recorded at.
+use std::{env, f32::consts::PI, fs, mem::size_of};
+
+use handmade_hero::{decode_wav, Arena, Mixer, SamplePlayer, Sound, SoundBuffer, Source};
+
+mod common;
+
+use common::write_wav;
+
+const OUTPUT_RATE: u32 = 48000;
+
+// A WAV file with a single format and data chunk
+fn wav_bytes(format: u16, num_channels: u16, sample_rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+    let block_align = num_channels * bits / 8;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&format.to_le_bytes());
+    bytes.extend_from_slice(&num_channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&bits.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn assert_samples(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (a - e).abs() < 1e-6,
+            "sample {}: expected {}, got {}",
+            i,
+            e,
+            a
+        );
+    }
+}
+
+#[test]
+fn decodes_integer_pcm() {
+    let expected = [0.0, 0.5, -0.5, -1.0];
+
+    let wav = decode_wav(&wav_bytes(1, 1, 8000, 8, &[128, 192, 64, 0])).unwrap();
+    assert_samples(&wav.samples, &expected);
+
+    let data: Vec<u8> = [0i16, 16384, -16384, -32768]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let wav = decode_wav(&wav_bytes(1, 1, 8000, 16, &data)).unwrap();
+    assert_samples(&wav.samples, &expected);
+
+    let data: Vec<u8> = [0i32, 0x40_0000, -0x40_0000, -0x80_0000]
+        .iter()
+        .flat_map(|s| s.to_le_bytes()[..3].to_vec())
+        .collect();
+    let wav = decode_wav(&wav_bytes(1, 1, 8000, 24, &data)).unwrap();
+    assert_samples(&wav.samples, &expected);
+}
+
+#[test]
+fn decodes_float_stereo() {
+    let data: Vec<u8> = [0.25f32, -0.75]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let wav = decode_wav(&wav_bytes(3, 2, 44100, 32, &data)).unwrap();
+    assert_eq!(wav.sample_rate, 44100);
+    assert_eq!(wav.num_channels, 2);
+    assert_samples(&wav.samples, &[0.25, -0.75]);
+
+    let data: Vec<u8> = [0.25f64, -0.75]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let wav = decode_wav(&wav_bytes(3, 2, 44100, 64, &data)).unwrap();
+    assert_samples(&wav.samples, &[0.25, -0.75]);
+}
+
+#[test]
+fn rejects_broken_files() {
+    assert!(decode_wav(b"not a wav file").is_err());
+    assert!(decode_wav(&wav_bytes(1, 6, 8000, 16, &[0; 12])).is_err());
+    assert!(decode_wav(&wav_bytes(2, 1, 8000, 4, &[0; 12])).is_err());
+
+    // Missing data chunk
+    let mut bytes = wav_bytes(1, 1, 8000, 16, &[]);
+    bytes.truncate(36);
+    assert!(decode_wav(&bytes).is_err());
+}
+
+#[test]
+fn reads_back_headless_output() {
+    let samples: Vec<f32> = (0..100).map(|i| (i as f32 / 50.0) - 1.0).collect();
+    let path = env::temp_dir().join(format!("handmade_hero_wav_{}.wav", std::process::id()));
+    write_wav(&path, &samples, 22050, 2).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let wav = decode_wav(&bytes).unwrap();
+    assert_eq!(wav.sample_rate, 22050);
+    assert_eq!(wav.num_channels, 2);
+    assert_samples(&wav.samples, &samples);
+}
+
+// Plays a `frequency` sine recorded at `sample_rate` and returns the
+// interleaved stereo output
+fn play_resampled(sample_rate: u32, frequency: f32, looping: bool, frames: usize) -> Vec<f32> {
+    let samples: Vec<f32> = (0..sample_rate / 10)
+        .map(|i| (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin() * 0.5)
+        .collect();
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    let wav = decode_wav(&wav_bytes(3, 1, sample_rate, 32, &data)).unwrap();
+
+    let mut memory = vec![0u64; samples.len()];
+    // `memory` outlives everything allocated from the arena
+    let mut arena = unsafe { Arena::new(memory.as_mut_ptr() as *mut u8, memory.len() * 8) };
+    let sound = Sound::from_wav(&wav, &mut arena).unwrap();
+
+    let mut mixer = Mixer::new();
+    let player = SamplePlayer::new(sound, looping);
+    mixer.play(Source::Sample(player), 1.0, 0.0, 0.0).unwrap();
+
+    let mut data = vec![0u8; frames * 2 * size_of::<f32>()];
+    mixer.mix(&mut SoundBuffer {
+        data: &mut data,
+        bytes_per_sample: size_of::<f32>(),
+        sample_rate: OUTPUT_RATE,
+        num_channels: 2,
+    });
+    data.chunks_exact(size_of::<f32>())
+        .map(|sample| f32::from_ne_bytes(sample.try_into().unwrap()))
+        .collect()
+}
+
+fn zero_crossings(samples: &[f32]) -> usize {
+    samples
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count()
+}
+
+#[test]
+fn resamples_to_output_rate() {
+    // A tenth of a second at any rate is still 10 periods of 100 Hz
+    for sample_rate in [8000, 22050, 44100, 96000] {
+        let samples = play_resampled(sample_rate, 100.0, false, OUTPUT_RATE as usize / 10);
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let crossings = zero_crossings(&left);
+        assert!(
+            (9..=10).contains(&crossings),
+            "{} Hz sound played {} periods",
+            sample_rate,
+            crossings
+        );
+        assert!(left.iter().all(|s| s.abs() <= 0.5 + 1e-6));
+    }
+}
+
+#[test]
+fn stops_at_end_unless_looping() {
+    let frames = OUTPUT_RATE as usize / 5;
+    let once = play_resampled(22050, 100.0, false, frames);
+    assert!(
+        once[frames..].iter().all(|s| *s == 0.0),
+        "kept playing past the end"
+    );
+
+    let looped = play_resampled(22050, 100.0, true, frames);
+    let left: Vec<f32> = looped.iter().step_by(2).copied().collect();
+    assert!((19..=20).contains(&zero_crossings(&left)));
+}